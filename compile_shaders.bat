@@ -2,3 +2,5 @@ glslc src/shaders/triangle.vert -o src/shaders/vert.spv
 glslc src/shaders/triangle.frag -o src/shaders/frag.spv
 glslc src/shaders/hud.vert -o src/shaders/hud_vert.spv
 glslc src/shaders/hud.frag -o src/shaders/hud_frag.spv
+glslc src/shaders/block.vert -o src/shaders/block_vert.spv
+glslc src/shaders/block.frag -o src/shaders/block_frag.spv
@@ -0,0 +1,47 @@
+// compares the meshers of `graphics::meshing` on generated terrain: number of vertices and indices, and meshing time.
+//
+// cargo run --release --example mesher_benchmark [radius in l1 segments, default 2]
+
+use std::time::{Duration, Instant};
+use citrus::graphics::meshing;
+use citrus::world::{World, Face, icoords::ICoords, segment::L1_SIZE_BL};
+
+fn main() {
+    let radius: i64 = std::env::args().nth(1).map_or(2, |r| r.parse().expect("the radius has to be an integer"));
+
+    let mut world = World::new();
+    let mut segments = Vec::new();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                segments.push(ICoords::new(x, y, z));
+            }
+        }
+    }
+    let start = Instant::now();
+    for &l1_glob in &segments {
+        world.generate_l1_segment(l1_glob * L1_SIZE_BL.into());
+    }
+    println!("generated {} l1 segments in {:.2?}\n", segments.len(), start.elapsed());
+
+    println!("{:<8} {:>10} {:>10} {:>10} {:>10} {:>12}", "mesher", "vertices", "indices", "MiB", "total", "per segment");
+    for mesher in meshing::meshers() {
+        let (mut n_vertices, mut n_indices, mut bytes) = (0, 0, 0);
+        let mut time = Duration::ZERO;
+        for &l1_glob in &segments {
+            let offset = l1_glob * L1_SIZE_BL.into();
+            let seg = world.l1_segment(offset).unwrap();
+            let neighbours = Face::all().map(|face| world.l1_segment(offset + face.numeric() * L1_SIZE_BL.into()));
+
+            let start = Instant::now();
+            let mesh = mesher.mesh(seg, neighbours, offset.vec3());
+            time += start.elapsed();
+            n_vertices += mesh.n_vertices();
+            n_indices += mesh.n_indices();
+            bytes += mesh.memory_usage();
+        }
+        let mib = bytes as f64 / (1024. * 1024.);
+        println!("{:<8} {:>10} {:>10} {:>10.2} {:>10.2?} {:>12.2?}",
+            mesher.name(), n_vertices, n_indices, mib, time, time / segments.len() as u32);
+    }
+}
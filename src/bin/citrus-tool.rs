@@ -0,0 +1,188 @@
+// headless world tool: generates or loads a world without opening a window, prints statistics,
+// saves it and exports its meshes. runs on machines without a gpu, e.g. in ci.
+//
+// cargo run --release --bin citrus-tool -- generate --seed 7 --radius 2 --stats --save saves/test --export terrain.glb
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use citrus::config;
+use citrus::graphics::{export::{ExportFormat, ExportMesh}, meshing};
+use citrus::profiler::*;
+use citrus::world::{World, icoords::ICoords, registry, segment::L1_SIZE_BL};
+
+const USAGE: &str = "usage:
+  citrus-tool generate [--seed <n>] [--radius <l1 segments>] [--center <x> <y> <z>] [options]
+  citrus-tool load <world directory> [options]
+
+generate creates the l1 segments within the radius (default 2) around the centre block (default 0 0 0).
+
+options:
+  --stats                print the number of segments and solid blocks
+  --save <directory>     save the world
+  --export <file>        export the meshes of all loaded segments as obj, ply, gltf or glb
+  --mesher <name>        mesher used by --export (default: the mesher in the config)
+  --blocks <file>        block definitions (default: the file in the config)
+  --profile              write the profiler summary and graph to profiles/";
+
+/// what to do, parsed from the command line
+struct Options {
+    source: Source,
+    stats: bool,
+    save: Option<PathBuf>,
+    export: Option<PathBuf>,
+    mesher: Option<String>,
+    blocks: PathBuf,
+    profile: bool,
+}
+
+enum Source {
+    Generate {
+        seed: u32,
+        radius: i64,
+        center: ICoords,
+    },
+    Load(PathBuf),
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter().map(String::as_str);
+        let mut source = match args.next() {
+            Some("generate") => Source::Generate { seed: config::WORLD_SEED, radius: 2, center: ICoords::new(0, 0, 0) },
+            Some("load") => Source::Load(PathBuf::from(args.next().ok_or("load needs the directory of a world")?)),
+            Some(command) => return Err(format!("unknown command `{}`", command)),
+            None => return Err(String::from("no command given")),
+        };
+        let mut options = Options {
+            source: Source::Load(PathBuf::new()),
+            stats: false,
+            save: None,
+            export: None,
+            mesher: None,
+            blocks: PathBuf::from(config::BLOCK_DEFINITIONS_PATH),
+            profile: false,
+        };
+
+        fn value<'a>(args: &mut impl Iterator<Item = &'a str>, option: &str) -> Result<&'a str, String> {
+            args.next().ok_or_else(|| format!("{} needs a value", option))
+        }
+        fn number<T: std::str::FromStr>(s: &str, option: &str) -> Result<T, String> {
+            s.parse().map_err(|_| format!("invalid value `{}` for {}", s, option))
+        }
+
+        while let Some(arg) = args.next() {
+            match (arg, &mut source) {
+                ("--seed", Source::Generate { seed, .. }) => *seed = number(value(&mut args, arg)?, arg)?,
+                ("--radius", Source::Generate { radius, .. }) => *radius = number(value(&mut args, arg)?, arg)?,
+                ("--center", Source::Generate { center, .. }) => {
+                    let mut coordinate = || number::<i64>(value(&mut args, arg)?, arg);
+                    *center = ICoords::new(coordinate()?, coordinate()?, coordinate()?);
+                },
+                ("--stats", _) => options.stats = true,
+                ("--save", _) => options.save = Some(PathBuf::from(value(&mut args, arg)?)),
+                ("--export", _) => {
+                    let path = PathBuf::from(value(&mut args, arg)?);
+                    if ExportFormat::from_path(&path).is_none() {
+                        return Err(format!("unknown mesh format of {:?}, available: {}", path, ExportFormat::EXTENSIONS.join(", ")));
+                    }
+                    options.export = Some(path);
+                },
+                ("--mesher", _) => {
+                    let name = value(&mut args, arg)?;
+                    if meshing::mesher(name).is_none() {
+                        let names: Vec<_> = meshing::meshers().iter().map(|m| m.name()).collect();
+                        return Err(format!("unknown mesher `{}`, available: {}", name, names.join(", ")));
+                    }
+                    options.mesher = Some(String::from(name));
+                },
+                ("--blocks", _) => options.blocks = PathBuf::from(value(&mut args, arg)?),
+                ("--profile", _) => options.profile = true,
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        options.source = source;
+        Ok(options)
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    registry::load_registry(&options.blocks).map_err(|e| format!("{:?}: {}", options.blocks, e))?;
+
+    let start = Instant::now();
+    let world = match &options.source {
+        Source::Generate { seed, radius, center } => {
+            let mut world = World::with_seed(*seed);
+            let center = center.l1_glob();
+            let mut segments = Vec::new();
+            for x in -radius..=*radius {
+                for y in -radius..=*radius {
+                    for z in -radius..=*radius {
+                        segments.push(center + ICoords::new(x, y, z));
+                    }
+                }
+            }
+            // from the top down, so that sky light passes through each column only once
+            segments.sort_by_key(|c| -c.y);
+            for &l1_glob in &segments {
+                world.generate_l1_segment(l1_glob * L1_SIZE_BL.into());
+            }
+            println!("generated {} l1 segments with seed {} in {:.2?}", segments.len(), seed, start.elapsed());
+            world
+        },
+        Source::Load(path) => {
+            let world = World::load(path).map_err(|e| format!("unable to load {:?}: {}", path, e))?;
+            println!("loaded {:?} with seed {} in {:.2?}", path, world.seed(), start.elapsed());
+            world
+        },
+    };
+
+    if options.stats {
+        let stats = world.stats();
+        println!("l3 segments:  {}", stats.l3_segments);
+        println!("l2 segments:  {}", stats.l2_segments);
+        println!("l1 segments:  {}", stats.l1_segments);
+        println!("solid blocks: {}", stats.solid_blocks);
+        println!("memory:       {:.2} MiB", stats.memory_usage as f64 / (1024. * 1024.));
+    }
+
+    if let Some(path) = &options.save {
+        world.save(path).map_err(|e| format!("unable to save to {:?}: {}", path, e))?;
+        println!("saved to {:?}", path);
+    }
+
+    if let Some(path) = &options.export {
+        let start = Instant::now();
+        let mesher = match options.mesher.as_deref().and_then(meshing::mesher) {
+            Some(mesher) => meshing::seeded(mesher, world.seed()),
+            None => world.mesher(),
+        };
+        let mesh = ExportMesh::from_world(&world, world.l1_segment_coords(), mesher.as_ref());
+        mesh.save(path).map_err(|e| format!("unable to export to {:?}: {}", path, e))?;
+        println!("exported {} triangles meshed with {} to {:?} in {:.2?}", mesh.n_triangles(), mesher.name(), path, start.elapsed());
+    }
+
+    if options.profile {
+        std::fs::create_dir_all(Path::new("profiles")).map_err(|e| format!("unable to create profiles/: {}", e))?;
+        p_summary();
+        p_graph(Duration::from_millis(10));
+        println!("wrote the profile to profiles/");
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let result = Options::parse(&args).and_then(|options| run(&options));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            ExitCode::FAILURE
+        },
+    }
+}
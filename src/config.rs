@@ -1,3 +1,35 @@
-
 pub const TICK_RATE: u32 = 64;
 
+
+// block definitions that are loaded into the block registry at startup
+pub const BLOCK_DEFINITIONS_PATH: &str = "./src/assets/blocks.txt";
+// directory with the block textures named in the block definitions, `<name>.png`
+pub const BLOCK_TEXTURES_PATH: &str = "./src/assets";
+// directory the world is loaded from at startup and saved to on exit
+pub const SAVE_PATH: &str = "./saves/world";
+// seed of newly created worlds, the terrain of each seed is shifted to a different part of the noise
+pub const WORLD_SEED: u32 = 0;
+
+// maximum number of bytes used by the undo history of world edits, the oldest operations are dropped beyond.
+// a single operation that would exceed it is refused.
+pub const HISTORY_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+// maximum number of blocks in the region of a single fill, replace, copy or paste.
+// edits whose undo history would exceed HISTORY_MEMORY_LIMIT are refused even if they are smaller.
+pub const MAX_EDIT_VOLUME: u64 = 1 << 24;
+
+// radius in l1 segments around the camera in which terrain is rendered
+pub const RENDER_DISTANCE: i64 = 4;
+// additional l1 segments beyond the render distance before a segment is unloaded
+pub const STREAMING_HYSTERESIS: i64 = 1;
+// maximum number of l1 segments that are being generated or meshed at the same time
+pub const STREAMING_BUDGET: usize = 64;
+
+// name of the mesher that l1 segments are meshed with (see `graphics::meshing::meshers`), can be changed at runtime.
+// greedy meshing produces far fewer vertices for flat terrain
+pub const MESHER: &str = "greedy";
+
+// the block meshers darken faces by the direction they face (see `meshing::Coloring`)
+pub const FACE_SHADING: bool = true;
+// seed of the noise that makes each block slightly brighter or darker, `None` for flat block colours.
+// tinted faces are only merged by the greedy mesher if they have the same tint, which more than doubles its vertices
+pub const TINT_SEED: Option<u64> = None;
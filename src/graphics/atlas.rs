@@ -0,0 +1,183 @@
+// block texture atlas: the textures named in the block registry are packed into one image as square tiles
+// of equal size, row by row. faces without a texture get a tile filled with the colour of their block,
+// so that every block face can be drawn with the same pipeline.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crate::world::{
+    Face,
+    block::BlockType,
+    registry::{self, BlockRegistry},
+    state::BlockState,
+};
+
+/// size of the tiles if the registry names no textures
+const DEFAULT_TILE_SIZE: u32 = 16;
+
+static ATLAS: OnceLock<TextureAtlas> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Image {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    TileSize {
+        path: PathBuf,
+        width: u32,
+        height: u32,
+        tile_size: u32,
+    },
+    AlreadyLoaded,
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::Image { path, error } => write!(f, "unable to load texture {:?}: {}", path, error),
+            AtlasError::TileSize { path, width, height, tile_size } =>
+                write!(f, "texture {:?} is {}x{}, all block textures have to be {}x{}", path, width, height, tile_size, tile_size),
+            AtlasError::AlreadyLoaded => write!(f, "the texture atlas has already been loaded"),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+#[derive(Debug)]
+pub struct TextureAtlas {
+    tile_size: u32,
+    tiles_per_row: u32,
+    rows: u32,
+    // rgba, 4 bytes per pixel, row by row
+    pixels: Vec<u8>,
+    // indexed by block id, the tile of each face in the order of `Face::all`
+    block_tiles: Vec<[u32; 6]>,
+}
+
+impl TextureAtlas {
+    /// packs the textures named in `registry`, loaded from `<dir>/<name>.png`, into one atlas
+    pub fn build(registry: &BlockRegistry, dir: &Path) -> Result<Self, AtlasError> {
+        let mut names: Vec<&str> = Vec::new();
+        for properties in registry.block_types().filter_map(|b| registry.get(b)) {
+            for face in Face::all() {
+                if let Some(name) = properties.texture(face) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+
+        let mut textures = Vec::new();
+        for name in &names {
+            let path = dir.join(format!("{}.png", name));
+            let texture = image::open(&path).map_err(|error| AtlasError::Image { path: path.clone(), error })?.to_rgba8();
+            let tile_size = textures.first().map_or(texture.width(), |first: &image::RgbaImage| first.width());
+            if texture.width() != tile_size || texture.height() != tile_size {
+                return Err(AtlasError::TileSize { path, width: texture.width(), height: texture.height(), tile_size });
+            }
+            textures.push(texture);
+        }
+        let tile_size = textures.first().map_or(DEFAULT_TILE_SIZE, |t| t.width());
+
+        // the textures come first, followed by one colour tile for each block with an untextured face
+        let tiles: HashMap<&str, u32> = names.iter().enumerate().map(|(i, &name)| (name, i as u32)).collect();
+        let mut colors = Vec::new();
+        let mut block_tiles = Vec::new();
+        for block in registry.block_types().filter(|&b| b != BlockType::NO_BLOCK) {
+            let Some(properties) = registry.get(block) else {
+                continue;
+            };
+            let mut color_tile = None;
+            let faces = Face::all().map(|face| match properties.texture(face) {
+                Some(name) => tiles[name],
+                None => *color_tile.get_or_insert_with(|| {
+                    colors.push(properties.color);
+                    (names.len() + colors.len() - 1) as u32
+                }),
+            });
+            let id = block.id() as usize;
+            if block_tiles.len() <= id {
+                block_tiles.resize(id + 1, [0; 6]);
+            }
+            block_tiles[id] = faces;
+        }
+
+        let n_tiles = (textures.len() + colors.len()).max(1) as u32;
+        let tiles_per_row = (n_tiles as f32).sqrt().ceil() as u32;
+        let rows = n_tiles.div_ceil(tiles_per_row);
+        let mut atlas = TextureAtlas {
+            tile_size,
+            tiles_per_row,
+            rows,
+            pixels: vec![0; (4 * tiles_per_row * tile_size * rows * tile_size) as usize],
+            block_tiles,
+        };
+        for (i, texture) in textures.iter().enumerate() {
+            atlas.fill_tile(i as u32, |x, y| texture.get_pixel(x, y).0);
+        }
+        for (i, color) in colors.iter().enumerate() {
+            let rgba = color.map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+            atlas.fill_tile((textures.len() + i) as u32, |_, _| rgba);
+        }
+        Ok(atlas)
+    }
+
+    fn fill_tile(&mut self, tile: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) {
+        let (tile_x, tile_y) = (tile % self.tiles_per_row * self.tile_size, tile / self.tiles_per_row * self.tile_size);
+        for y in 0..self.tile_size {
+            for x in 0..self.tile_size {
+                let i = 4 * ((tile_y + y) * self.width() + tile_x + x) as usize;
+                self.pixels[i..i + 4].copy_from_slice(&pixel(x, y));
+            }
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.tiles_per_row * self.tile_size
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows * self.tile_size
+    }
+
+    /// rgba, 4 bytes per pixel, row by row
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// the tile that is drawn on the `face` of `block` as seen from that direction, rotated blocks show their
+    /// top texture on the face their axis or facing points to
+    pub fn tile(&self, block: BlockState, face: Face) -> u32 {
+        self.block_tiles.get(block.block.id() as usize).map_or(0, |tiles| tiles[block.local_face(face) as usize])
+    }
+
+    /// atlas texture coordinates of the point `uv` within `tile`, where 0 0 is the top left corner of the tile and 1 1 the bottom right.
+    /// the tile is inset by half a pixel, so that sampling never bleeds into the neighbouring tiles.
+    pub fn uv(&self, tile: u32, [u, v]: [f32; 2]) -> [f32; 2] {
+        let inset = 0.5 / self.tile_size as f32;
+        let (column, row) = ((tile % self.tiles_per_row) as f32, (tile / self.tiles_per_row) as f32);
+        [
+            (column + inset + u * (1. - 2. * inset)) / self.tiles_per_row as f32,
+            (row + inset + v * (1. - 2. * inset)) / self.rows as f32,
+        ]
+    }
+}
+
+/// builds the atlas for the global block registry from the textures in `dir` and makes it the global atlas.
+/// has to be called after the registry has been loaded.
+pub fn load_atlas(dir: &Path) -> Result<(), AtlasError> {
+    let atlas = TextureAtlas::build(registry::registry(), dir)?;
+    ATLAS.set(atlas).map_err(|_| AtlasError::AlreadyLoaded)
+}
+
+/// the global texture atlas. it is built from `config::BLOCK_TEXTURES_PATH` if `load_atlas` has not been called.
+pub fn atlas() -> &'static TextureAtlas {
+    ATLAS.get_or_init(|| {
+        TextureAtlas::build(registry::registry(), Path::new(crate::config::BLOCK_TEXTURES_PATH))
+            .expect("unable to build the block texture atlas")
+    })
+}
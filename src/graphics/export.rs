@@ -0,0 +1,358 @@
+// mesh export for inspecting the terrain in other tools such as blender:
+// - wavefront `.obj`, with the vertex colours appended to the positions (`v x y z r g b`)
+// - binary little endian `.ply`, with 8 bit rgba vertex colours
+// - gltf 2.0, either as `.gltf` with the buffer embedded as a data uri or as binary `.glb`
+//
+// the vertices keep their global position, the meshes of different segments line up like in the game.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::world::{World, icoords::ICoords};
+use super::meshing::{Mesh, Mesher};
+use super::vertex::ColoredVertex;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Obj,
+    Ply,
+    /// json with the buffer embedded as base64
+    Gltf,
+    /// binary gltf
+    Glb,
+}
+
+impl ExportFormat {
+    pub const EXTENSIONS: [&'static str; 4] = ["obj", "ply", "gltf", "glb"];
+
+    /// the format of a file by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(OsStr::to_str)?.to_lowercase().as_str() {
+            "obj" => Some(ExportFormat::Obj),
+            "ply" => Some(ExportFormat::Ply),
+            "gltf" => Some(ExportFormat::Gltf),
+            "glb" => Some(ExportFormat::Glb),
+            _ => None,
+        }
+    }
+}
+
+/// coloured triangles in global coordinates, collected from the meshes of any number of segments
+#[derive(Clone, Debug, Default)]
+pub struct ExportMesh {
+    pub vertices: Vec<ColoredVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// meshes the loaded l1 segments at the global segment coordinates `segments` with `mesher`.
+    /// segments that are not loaded are skipped.
+    pub fn from_world(world: &World, segments: impl IntoIterator<Item = ICoords>, mesher: &dyn Mesher) -> Self {
+        let mut export = Self::new();
+        for l1_glob in segments {
+            if let Some(mesh) = world.mesh_l1_segment_with(l1_glob, mesher) {
+                export.append_mesh(&mesh.opaque);
+                export.append_mesh(&mesh.translucent);
+            }
+        }
+        export
+    }
+
+    /// appends a mesh as returned by `meshing::mesh_l1_segment`
+    pub fn append(&mut self, vertices: &[ColoredVertex], indices: &[u32]) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(indices.iter().map(|i| first + i));
+    }
+
+    /// appends any mesh. textured meshes have no colours, their vertices are grey with the brightness they are shaded with.
+    pub fn append_mesh(&mut self, mesh: &Mesh) {
+        match mesh {
+            Mesh::Colored(vertices, indices) => self.append(vertices, indices),
+            Mesh::Textured(vertices, indices) => {
+                let vertices: Vec<ColoredVertex> = vertices.iter().map(|v| ColoredVertex {
+                    pos: v.pos,
+                    color: [v.shade, v.shade, v.shade, 1.0],
+                    normal: crate::world::Face::all()[v.face as usize].numeric().vec3().to_array(),
+                }).collect();
+                self.append(&vertices, indices);
+            },
+        }
+    }
+
+    pub fn n_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// writes the mesh to `path` in the format given by its extension, see `ExportFormat::from_path`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let format = ExportFormat::from_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("unknown mesh format, the file name has to end in one of: {}", ExportFormat::EXTENSIONS.join(", "))))?;
+        let mut w = BufWriter::new(File::create(path)?);
+        match format {
+            ExportFormat::Obj => self.write_obj(&mut w)?,
+            ExportFormat::Ply => self.write_ply(&mut w)?,
+            ExportFormat::Gltf => self.write_gltf(&mut w)?,
+            ExportFormat::Glb => self.write_glb(&mut w)?,
+        }
+        w.flush()
+    }
+
+    pub fn write_obj(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "# exported from citrus, {} vertices, {} triangles", self.vertices.len(), self.n_triangles())?;
+        for v in &self.vertices {
+            let [x, y, z, _] = v.pos;
+            let [r, g, b, _] = v.color.map(|c| c.clamp(0., 1.));
+            writeln!(w, "v {} {} {} {} {} {}", x, y, z, r, g, b)?;
+        }
+        for v in &self.vertices {
+            let [x, y, z] = v.normal;
+            writeln!(w, "vn {} {} {}", x, y, z)?;
+        }
+        // indices are 1 based, each vertex has the normal with the same index
+        for t in self.indices.chunks_exact(3) {
+            let [a, b, c] = [t[0] + 1, t[1] + 1, t[2] + 1];
+            writeln!(w, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_ply(&self, w: &mut impl Write) -> io::Result<()> {
+        write!(w, "ply\n\
+            format binary_little_endian 1.0\n\
+            comment exported from citrus\n\
+            element vertex {}\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha\n\
+            element face {}\n\
+            property list uchar uint vertex_indices\n\
+            end_header\n", self.vertices.len(), self.n_triangles())?;
+        for v in &self.vertices {
+            for c in v.pos[..3].iter().chain(&v.normal) {
+                w.write_all(&c.to_le_bytes())?;
+            }
+            w.write_all(&color_bytes(v.color))?;
+        }
+        for t in self.indices.chunks_exact(3) {
+            w.write_all(&[3])?;
+            for i in t {
+                w.write_all(&i.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// gltf json with the buffer embedded as a base64 data uri
+    pub fn write_gltf(&self, w: &mut impl Write) -> io::Result<()> {
+        let buffer = self.gltf_buffer();
+        let uri = format!("data:application/octet-stream;base64,{}", base64(&buffer));
+        w.write_all(self.gltf_json(buffer.len(), Some(&uri)).as_bytes())
+    }
+
+    /// binary gltf: a json chunk followed by the buffer in a binary chunk
+    pub fn write_glb(&self, w: &mut impl Write) -> io::Result<()> {
+        let buffer = self.gltf_buffer();
+        let mut json = self.gltf_json(buffer.len(), None).into_bytes();
+        // chunks are aligned to 4 bytes, json with spaces and binary data with zeros
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let bin_length = buffer.len().next_multiple_of(4);
+        let has_bin = !buffer.is_empty();
+        let length = 12 + 8 + json.len() + if has_bin { 8 + bin_length } else { 0 };
+
+        w.write_all(b"glTF")?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&(length as u32).to_le_bytes())?;
+        w.write_all(&(json.len() as u32).to_le_bytes())?;
+        w.write_all(b"JSON")?;
+        w.write_all(&json)?;
+        if has_bin {
+            w.write_all(&(bin_length as u32).to_le_bytes())?;
+            w.write_all(b"BIN\0")?;
+            w.write_all(&buffer)?;
+            w.write_all(&vec![0; bin_length - buffer.len()])?;
+        }
+        Ok(())
+    }
+
+    /// positions, normals, colours and indices one after the other, see `gltf_json`
+    fn gltf_buffer(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let floats = self.vertices.iter().flat_map(|v| v.pos[..3].to_vec())
+            .chain(self.vertices.iter().flat_map(|v| v.normal))
+            .chain(self.vertices.iter().flat_map(|v| v.color.map(|c| c.clamp(0., 1.))));
+        for f in floats {
+            buffer.extend_from_slice(&f.to_le_bytes());
+        }
+        for i in &self.indices {
+            buffer.extend_from_slice(&i.to_le_bytes());
+        }
+        buffer
+    }
+
+    fn gltf_json(&self, buffer_length: usize, uri: Option<&str>) -> String {
+        let asset = r#""asset":{"version":"2.0","generator":"citrus"}"#;
+        // accessors must not be empty, an empty mesh is a scene without nodes
+        if self.is_empty() {
+            return format!(r#"{{{},"scene":0,"scenes":[{{"nodes":[]}}]}}"#, asset);
+        }
+
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        let n = self.vertices.len();
+        // (byte length, component type, type, target) of the positions, normals, colours and indices
+        let views = [
+            (12 * n, FLOAT, "VEC3", ARRAY_BUFFER),
+            (12 * n, FLOAT, "VEC3", ARRAY_BUFFER),
+            (16 * n, FLOAT, "VEC4", ARRAY_BUFFER),
+            (4 * self.indices.len(), UNSIGNED_INT, "SCALAR", ELEMENT_ARRAY_BUFFER),
+        ];
+
+        let mut offset = 0;
+        let mut buffer_views = Vec::new();
+        let mut accessors = Vec::new();
+        for (i, &(length, component_type, kind, target)) in views.iter().enumerate() {
+            buffer_views.push(format!(r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#, offset, length, target));
+            let count = if kind == "SCALAR" { self.indices.len() } else { n };
+            // the positions need their bounds
+            let bounds = if i == 0 {
+                let (min, max) = self.bounds();
+                format!(r#","min":[{},{},{}],"max":[{},{},{}]"#, min[0], min[1], min[2], max[0], max[1], max[2])
+            } else {
+                String::new()
+            };
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":{},"count":{},"type":"{}"{}}}"#, i, component_type, count, kind, bounds));
+            offset += length;
+        }
+        let uri = uri.map_or(String::new(), |uri| format!(r#","uri":"{}""#, uri));
+
+        format!(concat!(r#"{{{},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0,"name":"terrain"}}],"#,
+                        r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3,"mode":4}}]}}],"#,
+                        r#""buffers":[{{"byteLength":{}{}}}],"bufferViews":[{}],"accessors":[{}]}}"#),
+                asset, buffer_length, uri, buffer_views.join(","), accessors.join(","))
+    }
+
+    /// the minimum and maximum of the vertex positions
+    fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.vertices.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), v| {
+            ([0, 1, 2].map(|i| min[i].min(v.pos[i])), [0, 1, 2].map(|i| max[i].max(v.pos[i])))
+        })
+    }
+}
+
+fn color_bytes(color: [f32; 4]) -> [u8; 4] {
+    color.map(|c| (c.clamp(0., 1.) * 255.).round() as u8)
+}
+
+/// standard base64 with padding
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a unit square in the xz plane facing up
+    fn one_quad() -> ExportMesh {
+        let mut mesh = ExportMesh::new();
+        let vertices = [[0., 0.], [1., 0.], [1., 1.], [0., 1.]].map(|[x, z]| ColoredVertex {
+            pos: [x, 2., z, 1.],
+            color: [0.5, 1.5, 0., 1.],
+            normal: [0., 1., 0.],
+        });
+        mesh.append(&vertices, &[0, 1, 2, 0, 2, 3]);
+        mesh
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn base64_test_vectors() {
+        // RFC 4648, section 10
+        for (input, output) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64(input.as_bytes()), output, "{:?}", input);
+        }
+        assert_eq!(base64(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned() {
+        for (mesh, has_bin) in [(one_quad(), true), (ExportMesh::new(), false)] {
+            let mut bytes = Vec::new();
+            mesh.write_glb(&mut bytes).unwrap();
+            assert_eq!(&bytes[0..4], b"glTF");
+            assert_eq!(u32_at(&bytes, 4), 2);
+            assert_eq!(u32_at(&bytes, 8) as usize, bytes.len());
+            assert_eq!(bytes.len() % 4, 0);
+
+            let json_length = u32_at(&bytes, 12) as usize;
+            assert_eq!(json_length % 4, 0);
+            assert_eq!(&bytes[16..20], b"JSON");
+            let json = std::str::from_utf8(&bytes[20..20 + json_length]).unwrap();
+            assert!(json.starts_with('{') && json.trim_end().ends_with('}'));
+
+            let bin = &bytes[20 + json_length..];
+            if has_bin {
+                let bin_length = u32_at(bin, 0) as usize;
+                assert_eq!(bin_length % 4, 0);
+                assert_eq!(&bin[4..8], b"BIN\0");
+                assert_eq!(bin.len(), 8 + bin_length);
+                // 4 vertices with a position, normal and colour, and 6 indices
+                let buffer_length = 4 * (12 + 12 + 16) + 6 * 4;
+                assert!(bin_length >= buffer_length);
+                assert!(json.contains(&format!(r#""byteLength":{}"#, buffer_length)));
+            } else {
+                assert!(bin.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn ply_header_counts_the_elements() {
+        for (mesh, n_vertices, n_faces) in [(one_quad(), 4, 2), (ExportMesh::new(), 0, 0)] {
+            let mut bytes = Vec::new();
+            mesh.write_ply(&mut bytes).unwrap();
+            let end = b"end_header\n";
+            let header_length = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+            let header = std::str::from_utf8(&bytes[..header_length]).unwrap();
+            let lines: Vec<&str> = header.lines().collect();
+            assert_eq!(lines[0], "ply");
+            assert_eq!(lines[1], "format binary_little_endian 1.0");
+            assert!(lines.contains(&format!("element vertex {}", n_vertices).as_str()), "{}", header);
+            assert!(lines.contains(&format!("element face {}", n_faces).as_str()), "{}", header);
+            // 6 floats and 4 colour bytes per vertex, a count byte and 3 indices per face
+            assert_eq!(bytes.len() - header_length, n_vertices * (6 * 4 + 4) + n_faces * (1 + 3 * 4));
+        }
+        // colours are clamped to bytes
+        let mut bytes = Vec::new();
+        one_quad().write_ply(&mut bytes).unwrap();
+        let end = bytes.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+        assert_eq!(&bytes[end + 24..end + 28], &[128, 255, 0, 255]);
+    }
+}
@@ -1,20 +1,23 @@
 use crate::profiler::*;
 use std::usize;
+use std::mem::{size_of, size_of_val};
+use std::sync::Arc;
 
 use glam::Vec3;
-use rand::{
-    thread_rng,
-    Rng
-};
-
 use crate::{
-    graphics::vertex::ColoredVertex,
+    config,
+    graphics::{atlas, surface_nets::SmoothMesher, vertex::{BlockVertex, ColoredVertex}},
     world::{
         segment::{
             L1Segment,
             L1_SIZE_BL
         },
     size::Size2D,
+    light,
+    icoords::ICoords,
+    state::BlockState,
+    block::BlockType,
+    shape::{self, FaceMask},
     Face,
     BL_VERTICES
 }};
@@ -28,9 +31,9 @@ use crate::{
 //     }
 // }
 
-/// returns a `L1_SIZE_BL.x * L1_SIZE_BL.y` vector of u32 integers `a` which each encode what blocks are solid
+/// returns a `L1_SIZE_BL.x * L1_SIZE_BL.y` vector of u32 integers `a` which each encode for which blocks `pred` holds
 ///
-/// each bit in `a` encodes whether or not a block is solid (1) or not (0)
+/// each bit in `a` encodes whether `pred` holds for a block (1) or not (0)
 /// 
 /// example `a` in big endian order:
 /// ```
@@ -39,112 +42,895 @@ use crate::{
 /// 1 0 0 0 ... 0 1 1 0
 /// <---- 32 bits ---->
 /// ```
-fn l1_solids(seg: &L1Segment) -> Vec<u32> {
-    seg.blocks.chunks(L1_SIZE_BL.z as usize)
-        .map(|chunk| {
-            chunk.iter().enumerate().fold(0u32, |acc, (i, block)| { acc | ((block.is_solid() as u32) << i) })
-        }).collect()
+fn l1_bitmap(seg: &L1Segment, pred: impl Fn(&BlockState) -> bool) -> Vec<u32> {
+    let n_columns = (L1_SIZE_BL.x * L1_SIZE_BL.y) as usize;
+    if let Some(block) = seg.uniform() {
+        let column = if pred(&block) { u32::MAX } else { 0 };
+        return vec![column; n_columns];
+    }
+
+    let mut bitmap = vec![0u32; n_columns];
+    for (i, block) in seg.blocks().enumerate() {
+        bitmap[i / L1_SIZE_BL.z as usize] |= (pred(&block) as u32) << (i % L1_SIZE_BL.z as usize);
+    }
+    bitmap
 }
 
-/// - `neighbours`: [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
-pub fn mesh_l1_segment(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3) -> (Vec<ColoredVertex>, Vec<u32>) {
-    p_start("mesh_l1_segment.construct_bitmaps");
-    let solids = l1_solids(seg);
-    let neighbouring_solids = neighbours.map(|opt| opt.map_or(None, |neigh| Some(l1_solids(neigh))));
-    let plane_size = Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.y };
+fn is_visible(block: &BlockState) -> bool {
+    block.block != BlockType::NO_BLOCK
+}
+
+/// the vertices and triangle indices of a mesh. each vertex format is drawn with its own pipeline.
+pub enum Mesh {
+    Colored(Vec<ColoredVertex>, Vec<u32>),
+    Textured(Vec<BlockVertex>, Vec<u32>),
+}
+
+impl Mesh {
+    pub fn n_vertices(&self) -> usize {
+        match self {
+            Mesh::Colored(vertices, _) => vertices.len(),
+            Mesh::Textured(vertices, _) => vertices.len(),
+        }
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        match self {
+            Mesh::Colored(_, indices) | Mesh::Textured(_, indices) => indices,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n_vertices() == 0 || self.indices().is_empty()
+    }
+
+    /// bytes used by the vertices and indices
+    pub fn memory_usage(&self) -> usize {
+        let vertex_size = match self {
+            Mesh::Colored(..) => size_of::<ColoredVertex>(),
+            Mesh::Textured(..) => size_of::<BlockVertex>(),
+        };
+        self.n_vertices() * vertex_size + size_of_val(self.indices())
+    }
+}
+
+impl From<(Vec<ColoredVertex>, Vec<u32>)> for Mesh {
+    fn from((vertices, indices): (Vec<ColoredVertex>, Vec<u32>)) -> Self {
+        Mesh::Colored(vertices, indices)
+    }
+}
+
+impl From<(Vec<BlockVertex>, Vec<u32>)> for Mesh {
+    fn from((vertices, indices): (Vec<BlockVertex>, Vec<u32>)) -> Self {
+        Mesh::Textured(vertices, indices)
+    }
+}
+
+/// the mesh of an l1 segment, split into the faces of opaque and of translucent blocks (see `BlockType::is_translucent`).
+/// the translucent faces are drawn after all opaque ones with blending.
+pub struct SegmentMesh {
+    pub opaque: Mesh,
+    pub translucent: Mesh,
+}
+
+impl SegmentMesh {
+    pub fn n_vertices(&self) -> usize {
+        self.opaque.n_vertices() + self.translucent.n_vertices()
+    }
+
+    pub fn n_indices(&self) -> usize {
+        self.opaque.indices().len() + self.translucent.indices().len()
+    }
+
+    /// bytes used by the vertices and indices
+    pub fn memory_usage(&self) -> usize {
+        self.opaque.memory_usage() + self.translucent.memory_usage()
+    }
+}
+
+/// [opaque, translucent]
+impl<T: Into<Mesh>> From<[T; 2]> for SegmentMesh {
+    fn from([opaque, translucent]: [T; 2]) -> Self {
+        SegmentMesh { opaque: opaque.into(), translucent: translucent.into() }
+    }
+}
+
+/// turns an l1 segment into a mesh. implementations are chosen at runtime by name, see `mesher`.
+/// meshing runs on worker threads (see `jobs`), so meshers have to be shareable between threads.
+pub trait Mesher: Send + Sync {
+    /// the name the mesher is selected by
+    fn name(&self) -> &'static str;
+
+    /// returns the mesh of the segment `seg` whose 0 0 0 block is at `position`.
+    /// faces towards unloaded neighbours are left out.
+    /// - `neighbours`: [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
+    fn mesh(&self, seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3) -> SegmentMesh;
+
+    /// the mesher for a world generated with `seed`, `None` if the mesh does not depend on the generated terrain
+    fn for_seed(&self, _seed: u32) -> Option<Arc<dyn Mesher>> {
+        None
+    }
+}
+
+/// one quad per exposed block face, see `mesh_l1_segment`
+#[derive(Default)]
+pub struct NaiveMesher(pub Coloring);
+
+impl Mesher for NaiveMesher {
+    fn name(&self) -> &'static str {
+        "naive"
+    }
+
+    fn mesh(&self, seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3) -> SegmentMesh {
+        mesh_l1_segment(seg, neighbours, position, self.0).into()
+    }
+}
+
+/// adjacent coplanar faces that look the same are merged into larger quads, see `greedy_mesh_l1_segment`
+#[derive(Default)]
+pub struct GreedyMesher(pub Coloring);
+
+impl Mesher for GreedyMesher {
+    fn name(&self) -> &'static str {
+        "greedy"
+    }
+
+    fn mesh(&self, seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3) -> SegmentMesh {
+        greedy_mesh_l1_segment(seg, neighbours, position, self.0).into()
+    }
+}
+
+/// one quad per exposed block face, textured with the tiles of the global texture atlas, see `textured_mesh_l1_segment`
+#[derive(Default)]
+pub struct TexturedMesher(pub Coloring);
+
+impl Mesher for TexturedMesher {
+    fn name(&self) -> &'static str {
+        "textured"
+    }
+
+    fn mesh(&self, seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3) -> SegmentMesh {
+        textured_mesh_l1_segment(seg, neighbours, position, self.0).into()
+    }
+}
+
+/// all available meshers
+pub fn meshers() -> Vec<Arc<dyn Mesher>> {
+    vec![Arc::new(NaiveMesher::default()), Arc::new(GreedyMesher::default()), Arc::new(TexturedMesher::default()), Arc::new(SmoothMesher::default())]
+}
+
+/// the mesher called `name`, see `Mesher::name`
+pub fn mesher(name: &str) -> Option<Arc<dyn Mesher>> {
+    meshers().into_iter().find(|m| m.name() == name)
+}
+
+/// `mesher` for a world generated with `seed`, see `Mesher::for_seed`
+pub fn seeded(mesher: Arc<dyn Mesher>, seed: u32) -> Arc<dyn Mesher> {
+    mesher.for_seed(seed).unwrap_or(mesher)
+}
+
+/// opaque bitmaps of a segment and its neighbours, and for each face direction which blocks are exposed in that direction.
+/// every block except air is drawn, a face of a cube is exposed unless it is covered by the block in front of it (see `is_covered`).
+/// blocks of other shapes are drawn box by box, see `shape_quads`.
+struct ExposedFaces {
+    opaque: Vec<u32>,
+    neighbouring_opaque: [Option<Vec<u32>>; 6],
+    // visible blocks that are not cubes
+    shaped: Vec<u32>,
     // bit x, y, z indicates whether or not voxel x, y, z is exposed in what direction
-    let mut faces = vec![solids.clone(); Face::all().len()];
-    p_end("mesh_l1_segment.construct_bitmaps");
+    faces: Vec<Vec<u32>>,
+}
 
-    p_start("mesh_l1_segment.find_exposed_faces");
-    for (x, y) in plane_size {
-        let zpos_neigh = if let Some(neigh) = &neighbouring_solids[Face::ZPos as usize] { !(neigh[plane_size.c1d(x, y)] << 31) } else { !(1u32 << 31) };
-        faces[Face::ZPos as usize][plane_size.c1d(x, y)] &= !(solids[plane_size.c1d(x, y)] >> 1) & zpos_neigh;
+impl ExposedFaces {
+    fn new(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6]) -> Self {
+        p_start("mesh_l1_segment.construct_bitmaps");
+        let opaque = l1_bitmap(seg, BlockState::is_opaque);
+        let neighbouring_opaque = neighbours.map(|opt| opt.map(|neigh| l1_bitmap(neigh, BlockState::is_opaque)));
+        let plane_size = Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.y };
+        let shaped = l1_bitmap(seg, |b| is_visible(b) && !b.is_cube());
+        let visible = l1_bitmap(seg, is_visible);
+        let cubes: Vec<u32> = visible.iter().zip(&shaped).map(|(visible, shaped)| visible & !shaped).collect();
+        let mut faces = vec![cubes; Face::all().len()];
+        p_end("mesh_l1_segment.construct_bitmaps");
 
-        let zneg_neigh = if let Some(neigh) = &neighbouring_solids[Face::ZNeg as usize] { !(neigh[plane_size.c1d(x, y)] >> 31) } else { !1u32 };
-        faces[Face::ZNeg as usize][plane_size.c1d(x, y)] &= !(solids[plane_size.c1d(x, y)] << 1) & zneg_neigh;
+        p_start("mesh_l1_segment.find_exposed_faces");
+        for (x, y) in plane_size {
+            let zpos_neigh = if let Some(neigh) = &neighbouring_opaque[Face::ZPos as usize] { !(neigh[plane_size.c1d(x, y)] << 31) } else { !(1u32 << 31) };
+            faces[Face::ZPos as usize][plane_size.c1d(x, y)] &= !(opaque[plane_size.c1d(x, y)] >> 1) & zpos_neigh;
 
-        if x > 0 {
-            faces[Face::XNeg as usize][plane_size.c1d(x, y)] &= !solids[plane_size.c1d(x - 1, y)];
-        } else {
-            let xneg_neigh = if let Some(neigh) = &neighbouring_solids[Face::XNeg as usize] { !neigh[plane_size.c1d(L1_SIZE_BL.x as i64 - 1, y)] } else { 0u32 };
-            faces[Face::XNeg as usize][plane_size.c1d(x, y)] &= xneg_neigh;
+            let zneg_neigh = if let Some(neigh) = &neighbouring_opaque[Face::ZNeg as usize] { !(neigh[plane_size.c1d(x, y)] >> 31) } else { !1u32 };
+            faces[Face::ZNeg as usize][plane_size.c1d(x, y)] &= !(opaque[plane_size.c1d(x, y)] << 1) & zneg_neigh;
+
+            if x > 0 {
+                faces[Face::XNeg as usize][plane_size.c1d(x, y)] &= !opaque[plane_size.c1d(x - 1, y)];
+            } else {
+                let xneg_neigh = if let Some(neigh) = &neighbouring_opaque[Face::XNeg as usize] { !neigh[plane_size.c1d(L1_SIZE_BL.x as i64 - 1, y)] } else { 0u32 };
+                faces[Face::XNeg as usize][plane_size.c1d(x, y)] &= xneg_neigh;
+            }
+            if x < L1_SIZE_BL.x as i64 - 1 {
+                faces[Face::XPos as usize][plane_size.c1d(x, y)] &= !opaque[plane_size.c1d(x + 1, y)];
+            } else {
+                let xpos_neigh = if let Some(neigh) = &neighbouring_opaque[Face::XPos as usize] { !neigh[plane_size.c1d(0, y)] } else { 0u32 };
+                faces[Face::XPos as usize][plane_size.c1d(x, y)] &= xpos_neigh;
+            }
+
+            if y > 0 {
+                faces[Face::YNeg as usize][plane_size.c1d(x, y)] &= !opaque[plane_size.c1d(x, y - 1)];
+            } else {
+                let yneg_neigh = if let Some(neigh) = &neighbouring_opaque[Face::YNeg as usize] { !neigh[plane_size.c1d(x, L1_SIZE_BL.y as i64 - 1)] } else { 0u32 };
+                faces[Face::YNeg as usize][plane_size.c1d(x, y)] &= yneg_neigh;
+            }
+            if y < L1_SIZE_BL.y as i64 - 1 {
+                faces[Face::YPos as usize][plane_size.c1d(x, y)] &= !opaque[plane_size.c1d(x, y + 1)];
+            } else {
+                let ypos_neigh = if let Some(neigh) = &neighbouring_opaque[Face::YPos as usize] { !neigh[plane_size.c1d(x, 0)] } else { 0u32 };
+                faces[Face::YPos as usize][plane_size.c1d(x, y)] &= ypos_neigh;
+            }
         }
-        if x < L1_SIZE_BL.x as i64 - 1 {
-            faces[Face::XPos as usize][plane_size.c1d(x, y)] &= !solids[plane_size.c1d(x + 1, y)];
-        } else {
-            let xpos_neigh = if let Some(neigh) = &neighbouring_solids[Face::XPos as usize] { !neigh[plane_size.c1d(0, y)] } else { 0u32 };
-            faces[Face::XPos as usize][plane_size.c1d(x, y)] &= xpos_neigh;
+
+        // faces in front of see-through blocks and blocks that are not cubes may still be covered
+        if seg.uniform() != Some(BlockState::NO_BLOCK) {
+            // air in front of a face never covers it, blocks in the neighbours are looked up
+            let maybe_covering = |c: ICoords| !L1_SIZE_BL.contains(c) || visible[plane_size.c1d(c.x, c.y)] & (1u32 << c.z) != 0;
+            for (x, y) in plane_size {
+                let i = plane_size.c1d(x, y);
+                let column = faces.iter().fold(0, |column, f| column | f[i]);
+                for z in (0..L1_SIZE_BL.z as i64).filter(|z| column & (1u32 << z) != 0) {
+                    let coords = ICoords::new(x, y, z);
+                    for face in Face::all() {
+                        let in_front = coords + face.numeric();
+                        if faces[face as usize][i] & (1u32 << z) != 0 && maybe_covering(in_front)
+                            && block_at(seg, &neighbours, in_front).is_some_and(|b| is_covered(seg.get(coords), face, &FaceMask::FULL, b)) {
+                            faces[face as usize][i] &= !(1u32 << z);
+                        }
+                    }
+                }
+            }
         }
+        p_end("mesh_l1_segment.find_exposed_faces");
+
+        ExposedFaces { opaque, neighbouring_opaque, shaped, faces }
+    }
+
+    fn is_exposed(&self, coords: ICoords, face: Face) -> bool {
+        let plane_size = Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.y };
+        self.faces[face as usize][plane_size.c1d(coords.x, coords.y)] & (1u32 << coords.z) != 0
+    }
+
+    fn is_opaque(&self, c: ICoords) -> bool {
+        opaque_at(&self.opaque, &self.neighbouring_opaque, c)
+    }
+
+    /// local coordinates of the visible blocks that are not cubes
+    fn shaped_blocks(&self) -> impl Iterator<Item = ICoords> + '_ {
+        let plane_size = Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.y };
+        plane_size.into_iter()
+            .filter(move |&(x, y)| self.shaped[plane_size.c1d(x, y)] != 0)
+            .flat_map(move |(x, y)| {
+                let column = self.shaped[plane_size.c1d(x, y)];
+                (0..L1_SIZE_BL.z as i64).filter(move |z| column & (1u32 << z) != 0).map(move |z| ICoords::new(x, y, z))
+            })
+    }
+}
+
+/// whether the part `mask` of the side towards `face` of the voxel of `block` is hidden by the block `in_front` of it.
+/// that is the case if `in_front` covers all of `mask` and is either opaque or of the same type,
+/// like two blocks of water or a stone block below a slab.
+fn is_covered(block: BlockState, face: Face, mask: &FaceMask, in_front: BlockState) -> bool {
+    is_visible(&in_front)
+        && (in_front.is_solid() && !in_front.block.is_transparent() || in_front.block == block.block)
+        && in_front.face_coverage(face.opposite()).covers(mask)
+}
+
+/// a quad of a block that is not a cube, see `shape_quads`
+struct ShapeQuad {
+    /// the face whose shading and texture the quad gets
+    face: Face,
+    normal: [f32; 3],
+    /// relative to the 0 0 0 corner of the block, in winding order
+    corners: [[f32; 3]; 4],
+    /// texture coordinates within the tile of `face`
+    uvs: [[f32; 2]; 4],
+    light: u8,
+}
+
+/// the visible quads of `block` at local coordinates `coords`, drawn by its boxes (see `BlockState::boxes`).
+/// a side of a box is left out if it is covered by the other boxes of the block or, if it lies on the side of the voxel,
+/// by the block in front of it (see `is_covered`). crossed plants are drawn from both sides.
+/// the quads are not darkened by ambient occlusion.
+fn shape_quads(seg: &L1Segment, neighbours: &[Option<&L1Segment>; 6], block: BlockState, coords: ICoords) -> Vec<ShapeQuad> {
+    if block.block.properties().shape == shape::BlockShape::Cross {
+        // the two diagonals of the voxel, each with its corners in winding order when seen from both sides
+        let light = seg.light(coords);
+        let diagonals = [
+            ([[0., 0., 0.], [1., 0., 1.], [1., 1., 1.], [0., 1., 0.]], [1., 0., -1.]),
+            ([[1., 0., 0.], [0., 0., 1.], [0., 1., 1.], [1., 1., 0.]], [1., 0., 1.]),
+        ];
+        return diagonals.into_iter().flat_map(|([a, b, c, d], [nx, ny, nz]): ([[f32; 3]; 4], [f32; 3])| {
+            let normal = Vec3::new(nx, ny, nz).normalize().to_array();
+            // the texture is mirrored on the back, so that it is not seen mirrored from either side
+            [
+                ([a, b, c, d], normal, [[0., 1.], [1., 1.], [1., 0.], [0., 0.]]),
+                ([a, d, c, b], normal.map(|n| -n), [[1., 1.], [1., 0.], [0., 0.], [0., 1.]]),
+            ]
+        }).map(|(corners, normal, uvs)| ShapeQuad { face: Face::XPos, normal, corners, uvs, light }).collect();
+    }
 
-        if y > 0 {
-            faces[Face::YNeg as usize][plane_size.c1d(x, y)] &= !solids[plane_size.c1d(x, y - 1)];
-        } else {
-            let yneg_neigh = if let Some(neigh) = &neighbouring_solids[Face::YNeg as usize] { !neigh[plane_size.c1d(x, L1_SIZE_BL.y as i64 - 1)] } else { 0u32 };
-            faces[Face::YNeg as usize][plane_size.c1d(x, y)] &= yneg_neigh;
+    let boxes = block.boxes();
+    let mut quads = Vec::new();
+    for b in &boxes {
+        for face in Face::all() {
+            let side = b.side(face);
+            let mask = b.face_mask(face);
+            let light = if side == shape::voxel_side(face) {
+                match block_at(seg, neighbours, coords + face.numeric()) {
+                    Some(in_front) if !is_covered(block, face, &mask, in_front) => face_light(seg, neighbours, coords, face),
+                    // faces towards unloaded neighbours are left out like those of cubes
+                    _ => continue,
+                }
+            } else if shape::coverage(&boxes, face.opposite(), side).covers(&mask) {
+                continue;
+            } else {
+                seg.light(coords)
+            };
+            let corners = face_corners(face).map(|corner| {
+                let c = BL_VERTICES[corner];
+                [0, 1, 2].map(|i| if c[i] == 1 { b.max[i] } else { b.min[i] } as f32 / shape::SHAPE_UNITS as f32)
+            });
+            quads.push(ShapeQuad {
+                face,
+                normal: face.numeric().vec3().to_array(),
+                corners,
+                uvs: corners.map(|corner| face_uv(face, corner)),
+                light,
+            });
         }
-        if y < L1_SIZE_BL.y as i64 - 1 {
-            faces[Face::YPos as usize][plane_size.c1d(x, y)] &= !solids[plane_size.c1d(x, y + 1)];
-        } else {
-            let ypos_neigh = if let Some(neigh) = &neighbouring_solids[Face::YPos as usize] { !neigh[plane_size.c1d(x, 0)] } else { 0u32 };
-            faces[Face::YPos as usize][plane_size.c1d(x, y)] &= ypos_neigh;
+    }
+    quads
+}
+
+/// the packed light level in front of the exposed `face` of the block at `coords`
+fn face_light(seg: &L1Segment, neighbours: &[Option<&L1Segment>; 6], coords: ICoords, face: Face) -> u8 {
+    let in_front = coords + face.numeric();
+    if L1_SIZE_BL.contains(in_front) {
+        seg.light(in_front)
+    } else {
+        // exposed faces at the border always have a loaded neighbour
+        neighbours[face as usize].map_or(0, |neigh| neigh.light(in_front.bl_loc()))
+    }
+}
+
+/// brightness of faces by their direction in the order of `Face::all` if face shading is enabled, as if lit from above
+const FACE_SHADE: [f32; 6] = [0.8, 0.8, 1.0, 0.5, 0.9, 0.9];
+/// number of different tints, blocks are only merged by the greedy mesher if they have the same tint
+const TINT_LEVELS: u64 = 4;
+/// the brightness of the tints lies between `1 - TINT_STRENGTH` and `1 + TINT_STRENGTH`
+const TINT_STRENGTH: f32 = 0.06;
+
+/// how the block meshers colour faces, starting from the colour of the block in the registry.
+/// the colour only depends on the block, its position and the face, so the same segment always gets the same mesh.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Coloring {
+    /// darkens faces by the direction they face, see `FACE_SHADE`
+    pub face_shading: bool,
+    /// seed of the noise that makes each block slightly brighter or darker, `None` for flat block colours
+    pub tint_seed: Option<u64>,
+}
+
+impl Default for Coloring {
+    /// the colouring set in the config
+    fn default() -> Self {
+        Coloring {
+            face_shading: config::FACE_SHADING,
+            tint_seed: config::TINT_SEED,
         }
     }
-    p_end("mesh_l1_segment.find_exposed_faces");
+}
 
-    p_start("mesh_l1_segment.find_needed_indices");
-    // now we know which faces are exposed
-    let mut indices = Vec::<u32>::new();
+impl Coloring {
+    /// the tint of the block at global coordinates `coords`, from 0 to `TINT_LEVELS - 1`
+    fn tint(&self, coords: ICoords) -> u64 {
+        let Some(seed) = self.tint_seed else {
+            return TINT_LEVELS / 2;
+        };
+        // splitmix64 of the seed and the coordinates
+        let mut h = seed ^ (coords.x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (coords.y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+            ^ (coords.z as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (h ^ (h >> 31)) % TINT_LEVELS
+    }
+
+    /// brightness factor of `face` of a block with the given `tint`
+    fn brightness(&self, face: Face, tint: u64) -> f32 {
+        let shade = if self.face_shading { FACE_SHADE[face as usize] } else { 1.0 };
+        let tint = match self.tint_seed {
+            Some(_) => 1.0 + TINT_STRENGTH * (2.0 * tint as f32 / (TINT_LEVELS - 1) as f32 - 1.0),
+            None => 1.0,
+        };
+        shade * tint
+    }
+
+    /// the colour of `face` of `block` at global coordinates `coords`, before light and ambient occlusion
+    fn color(&self, block: BlockState, coords: ICoords, face: Face) -> [f32; 4] {
+        let [r, g, b, a] = block.block.color().unwrap_or([1.0; 4]);
+        let brightness = self.brightness(face, self.tint(coords));
+        [r * brightness, g * brightness, b * brightness, a]
+    }
+}
+
+/// the two triangles of a quad with the ambient occlusion `ao` at its corners, as indices into the corners
+fn quad_triangles(ao: [usize; 4]) -> [u32; 6] {
+    // split the quad along the diagonal through the darker corners, so that the occlusion is interpolated symmetrically
+    if ao[0] + ao[2] < ao[1] + ao[3] { [0, 1, 2, 0, 2, 3] } else { [1, 2, 3, 1, 3, 0] }
+}
+
+/// appends a quad facing `normal` with the given corners in winding order, shaded by `brightness` and the ambient occlusion `ao` of each corner
+fn push_quad(vertices: &mut Vec<ColoredVertex>, indices: &mut Vec<u32>, normal: [f32; 3], corners: [[f32; 3]; 4], ao: [usize; 4], color: [f32; 4], brightness: f32) {
+    let first = vertices.len() as u32;
+    indices.extend(quad_triangles(ao).iter().map(|idx| first + idx));
+    let [r, g, b, a] = color;
+    vertices.extend(corners.iter().zip(ao).map(|(&[x, y, z], ao)| {
+        let shade = brightness * AO_BRIGHTNESS[ao];
+        ColoredVertex {
+            pos: [x, y, z, 1.0],
+            color: [r * shade, g * shade, b * shade, a],
+            normal,
+        }
+    }));
+}
+
+/// emits one quad per exposed block face
+/// - `neighbours`: [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
+pub fn mesh_l1_segment(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3, coloring: Coloring) -> [(Vec<ColoredVertex>, Vec<u32>); 2] {
+    let exposed = ExposedFaces::new(seg, neighbours);
+    let origin = ICoords::from_vec3(position);
+
+    p_start("mesh_l1_segment.create_vertex_array");
+    // every face gets its own vertices, coloured by its block and the light in front of it
+    // and darkened by ambient occlusion at each corner
+    let corners = Face::all().map(face_corners);
+    // [opaque, translucent]
+    let mut parts: [(Vec<ColoredVertex>, Vec<u32>); 2] = Default::default();
+    for coords in L1_SIZE_BL {
+        for face in Face::all() {
+            if !exposed.is_exposed(coords, face) {
+                continue;
+            }
+            let brightness = light::brightness(face_light(seg, &neighbours, coords, face));
+            let quad = corners[face as usize];
+            let ao = quad.map(|corner| corner_occlusion(face, BL_VERTICES[corner], coords + face.numeric(), |c| exposed.is_opaque(c)));
+            let positions = quad.map(|corner| corner_position(coords, corner, position));
+            let block = seg.get(coords);
+            let color = coloring.color(block, origin + coords, face);
+            let (vertices, indices) = &mut parts[block.block.is_translucent() as usize];
+            push_quad(vertices, indices, face.numeric().vec3().to_array(), positions, ao, color, brightness);
+        }
+    }
+    push_shapes(&mut parts, seg, &neighbours, &exposed, position, coloring);
+    p_end("mesh_l1_segment.create_vertex_array");
+
+    parts
+}
+
+/// emits one quad per exposed block face like `mesh_l1_segment`, textured with the tile of the global atlas
+/// for the face of the block
+/// - `neighbours`: [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
+pub fn textured_mesh_l1_segment(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3, coloring: Coloring) -> [(Vec<BlockVertex>, Vec<u32>); 2] {
+    let exposed = ExposedFaces::new(seg, neighbours);
+    let atlas = atlas::atlas();
+    let origin = ICoords::from_vec3(position);
+
+    p_start("textured_mesh_l1_segment.create_vertex_array");
+    let corners = Face::all().map(face_corners);
+    // [opaque, translucent]
+    let mut parts: [(Vec<BlockVertex>, Vec<u32>); 2] = Default::default();
     for coords in L1_SIZE_BL {
-        let v = 8 * L1_SIZE_BL.c1d(coords);
         for face in Face::all() {
-            if faces[face as usize][plane_size.c1d(coords.x, coords.y)] & (1u32 << coords.z) != 0 {
-                indices.extend_from_slice(&face.indices().map(|idx| {v as u32 + idx}));
+            if !exposed.is_exposed(coords, face) {
+                continue;
             }
+            let brightness = light::brightness(face_light(seg, &neighbours, coords, face))
+                * coloring.brightness(face, coloring.tint(origin + coords));
+            let quad = corners[face as usize];
+            let ao = quad.map(|corner| corner_occlusion(face, BL_VERTICES[corner], coords + face.numeric(), |c| exposed.is_opaque(c)));
+            let block = seg.get(coords);
+            let tile = atlas.tile(block, face);
+
+            let (vertices, indices) = &mut parts[block.block.is_translucent() as usize];
+            let first = vertices.len() as u32;
+            indices.extend(quad_triangles(ao).iter().map(|idx| first + idx));
+            vertices.extend(quad.iter().zip(ao).map(|(&corner, ao)| {
+                let [x, y, z] = corner_position(coords, corner, position);
+                BlockVertex {
+                    pos: [x, y, z, 1.0],
+                    tex_coord: atlas.uv(tile, face_uv(face, BL_VERTICES[corner].map(|c| c as f32))),
+                    shade: brightness * AO_BRIGHTNESS[ao],
+                    face: face as u32,
+                }
+            }));
         }
     }
-    p_end("mesh_l1_segment.find_needed_indices");
-    p_start("mesh_l1_segment.find_needed_vertices");
-    // check which vertices are actually used
-    let mut vertex_used = vec![false; 8 * L1_SIZE_BL.volume() as usize];
-    for &index in &indices {
-        vertex_used[index as usize] = true;
+    for coords in exposed.shaped_blocks() {
+        let block = seg.get(coords);
+        let (vertices, indices) = &mut parts[block.block.is_translucent() as usize];
+        for quad in shape_quads(seg, &neighbours, block, coords) {
+            let shade = light::brightness(quad.light) * coloring.brightness(quad.face, coloring.tint(origin + coords));
+            let tile = atlas.tile(block, quad.face);
+            let first = vertices.len() as u32;
+            indices.extend(quad_triangles([3; 4]).iter().map(|idx| first + idx));
+            vertices.extend(quad.corners.iter().zip(quad.uvs).map(|(&corner, uv)| {
+                let [x, y, z] = shape_corner_position(coords, corner, position);
+                BlockVertex {
+                    pos: [x, y, z, 1.0],
+                    tex_coord: atlas.uv(tile, uv),
+                    shade,
+                    face: quad.face as u32,
+                }
+            }));
+        }
     }
-    p_end("mesh_l1_segment.find_needed_vertices");
+    p_end("textured_mesh_l1_segment.create_vertex_array");
+
+    parts
+}
 
-    p_start("mesh_l1_segment.create_permutation");
-    let mut permutation = vec![0 as usize; vertex_used.len()];
-    let mut n_vertices_used = 0;
-    for (i, &used) in vertex_used.iter().enumerate() {
-        if used {
-            permutation[i] = n_vertices_used;
-            n_vertices_used += 1;
+/// global position of the corner `corner` (see `BL_VERTICES`) of the block at local coordinates `coords`
+fn corner_position(coords: ICoords, corner: usize, position: Vec3) -> [f32; 3] {
+    let [dx, dy, dz] = BL_VERTICES[corner];
+    [
+        (coords.x as u64 + dx) as f32 + position.x,
+        (coords.y as u64 + dy) as f32 + position.y,
+        (coords.z as u64 + dz) as f32 + position.z,
+    ]
+}
+
+/// global position of the point `corner`, relative to the 0 0 0 corner of the block at local coordinates `coords`
+fn shape_corner_position(coords: ICoords, [dx, dy, dz]: [f32; 3], position: Vec3) -> [f32; 3] {
+    [
+        coords.x as f32 + dx + position.x,
+        coords.y as f32 + dy + position.y,
+        coords.z as f32 + dz + position.z,
+    ]
+}
+
+/// appends the quads of the blocks that are not cubes (see `shape_quads`) to the coloured [opaque, translucent] `parts`
+fn push_shapes(parts: &mut [(Vec<ColoredVertex>, Vec<u32>); 2], seg: &L1Segment, neighbours: &[Option<&L1Segment>; 6],
+               exposed: &ExposedFaces, position: Vec3, coloring: Coloring) {
+    let origin = ICoords::from_vec3(position);
+    for coords in exposed.shaped_blocks() {
+        let block = seg.get(coords);
+        let (vertices, indices) = &mut parts[block.block.is_translucent() as usize];
+        for quad in shape_quads(seg, neighbours, block, coords) {
+            let color = coloring.color(block, origin + coords, quad.face);
+            let corners = quad.corners.map(|corner| shape_corner_position(coords, corner, position));
+            push_quad(vertices, indices, quad.normal, corners, [3; 4], color, light::brightness(quad.light));
         }
     }
-    p_end("mesh_l1_segment.create_permutation");
+}
 
-    p_start("mesh_l1_segment.create_vertex_array");
-    let mut rng = thread_rng();
-    let colors = (0..217).map(|_| [rng.gen(), rng.gen(), rng.gen(), 0.8]).collect::<Vec<_>>();
-    let vertices = (0 .. 8 * L1_SIZE_BL.volume() as usize).filter(|&i| vertex_used[i]).map(|i| {
-        let [dx, dy, dz] = BL_VERTICES[i % 8];
-        let coords = L1_SIZE_BL.c3d(i as u64 / 8);
-        ColoredVertex {
-            pos: [
-                (coords.x as u64 + dx) as f32 + position.x,
-                (coords.y as u64 + dy) as f32 + position.y,
-                (coords.z as u64 + dz) as f32 + position.z,
-                1.0
-            ],
-            color: colors[(i as usize / 8) % colors.len()],
-        }
-    }).collect();
-    p_end("mesh_l1_segment.create_vertex_array");
+/// texture coordinates within a tile of the point `corner` on `face`, relative to the 0 0 0 corner of the block.
+/// the top of the texture points up on the side faces and, seen from outside, no face is mirrored.
+fn face_uv(face: Face, [x, y, z]: [f32; 3]) -> [f32; 2] {
+    match face {
+        Face::XPos => [1. - z, 1. - y],
+        Face::XNeg => [z, 1. - y],
+        Face::YPos => [x, z],
+        Face::YNeg => [x, 1. - z],
+        Face::ZPos => [x, 1. - y],
+        Face::ZNeg => [1. - x, 1. - y],
+    }
+}
+
+/// what a face looks like. only faces that look the same are merged by the greedy mesher,
+/// faces with uneven ambient occlusion are never merged because the occlusion is interpolated across the quad.
+#[derive(Clone, Copy, PartialEq)]
+struct FaceKey {
+    block: BlockState,
+    light: u8,
+    ao: [usize; 4],
+    tint: u64,
+}
 
-    // translate indices
-    for i in 0..indices.len() {
-        indices[i] = permutation[indices[i] as usize] as u32;
+impl FaceKey {
+    fn mergeable(&self) -> bool {
+        self.ao.iter().all(|&ao| ao == self.ao[0])
     }
+}
+
+/// merges adjacent coplanar exposed faces of the same block, light and ambient occlusion into larger quads.
+/// every slice of blocks perpendicular to a face direction is covered row by row with the largest rectangles
+/// that start at the first face not yet covered.
+/// - `neighbours`: [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
+pub fn greedy_mesh_l1_segment(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3, coloring: Coloring) -> [(Vec<ColoredVertex>, Vec<u32>); 2] {
+    let exposed = ExposedFaces::new(seg, neighbours);
+    let origin = ICoords::from_vec3(position);
+
+    p_start("greedy_mesh_l1_segment.merge_faces");
+    let size: ICoords = L1_SIZE_BL.into();
+    // [opaque, translucent]
+    let mut parts: [(Vec<ColoredVertex>, Vec<u32>); 2] = Default::default();
+    for face in Face::all() {
+        let quad = face_corners(face);
+        // the axis of the face normal and the two axes spanning the slice, as indices into [x, y, z]
+        let normal = face.numeric();
+        let n_axis = [normal.x, normal.y, normal.z].iter().position(|&n| n != 0).unwrap();
+        let (u_axis, v_axis) = ((n_axis + 1) % 3, (n_axis + 2) % 3);
+        let (n_size, u_size, v_size) = (axis(size, n_axis), axis(size, u_axis), axis(size, v_axis));
+        let cell = |d: i64, u: i64, v: i64| {
+            let mut c = [0; 3];
+            c[n_axis] = d;
+            c[u_axis] = u;
+            c[v_axis] = v;
+            ICoords::new(c[0], c[1], c[2])
+        };
 
-    (vertices, indices)
+        let mut keys: Vec<Option<FaceKey>> = vec![None; (u_size * v_size) as usize];
+        for d in 0..n_size {
+            for u in 0..u_size {
+                for v in 0..v_size {
+                    let coords = cell(d, u, v);
+                    keys[(u * v_size + v) as usize] = exposed.is_exposed(coords, face).then(|| FaceKey {
+                        block: seg.get(coords),
+                        light: face_light(seg, &neighbours, coords, face),
+                        ao: quad.map(|corner| corner_occlusion(face, BL_VERTICES[corner], coords + normal, |c| exposed.is_opaque(c))),
+                        tint: coloring.tint(origin + coords),
+                    });
+                }
+            }
+
+            for u in 0..u_size {
+                let mut v = 0;
+                while v < v_size {
+                    let Some(key) = keys[(u * v_size + v) as usize] else {
+                        v += 1;
+                        continue;
+                    };
+                    let key_at = |u: i64, v: i64| keys[(u * v_size + v) as usize];
+                    // grow along v first, then along u as long as the whole row matches
+                    let (mut width, mut height) = (1, 1);
+                    if key.mergeable() {
+                        while v + height < v_size && key_at(u, v + height) == Some(key) {
+                            height += 1;
+                        }
+                        while u + width < u_size && (v..v + height).all(|v| key_at(u + width, v) == Some(key)) {
+                            width += 1;
+                        }
+                    }
+                    for du in 0..width {
+                        for dv in 0..height {
+                            keys[((u + du) * v_size + v + dv) as usize] = None;
+                        }
+                    }
+
+                    let start = cell(d, u, v);
+                    let positions = quad.map(|corner| {
+                        let offset = BL_VERTICES[corner].map(|o| o as i64);
+                        let mut p = [start.x, start.y, start.z];
+                        p[n_axis] += offset[n_axis];
+                        p[u_axis] += offset[u_axis] * width;
+                        p[v_axis] += offset[v_axis] * height;
+                        [p[0] as f32 + position.x, p[1] as f32 + position.y, p[2] as f32 + position.z]
+                    });
+                    let color = coloring.color(key.block, origin + start, face);
+                    let (vertices, indices) = &mut parts[key.block.block.is_translucent() as usize];
+                    push_quad(vertices, indices, normal.vec3().to_array(), positions, key.ao, color, light::brightness(key.light));
+                    v += height;
+                }
+            }
+        }
+    }
+    // blocks that are not cubes are never merged
+    push_shapes(&mut parts, seg, &neighbours, &exposed, position, coloring);
+    p_end("greedy_mesh_l1_segment.merge_faces");
+
+    parts
+}
+
+fn axis(c: ICoords, i: usize) -> i64 {
+    [c.x, c.y, c.z][i]
+}
+
+/// the block at local block coordinates `c`, which may lie in one of the face `neighbours`.
+/// `None` if it lies in an unloaded neighbour or in a segment that only shares an edge or corner.
+pub(crate) fn block_at(seg: &L1Segment, neighbours: &[Option<&L1Segment>; 6], c: ICoords) -> Option<BlockState> {
+    let (offset, local) = (c.l1_glob(), c.bl_loc());
+    if offset == ICoords::new(0, 0, 0) {
+        return Some(seg.get(local));
+    }
+    Face::all().into_iter()
+        .find(|face| face.numeric() == offset)
+        .and_then(|face| neighbours[face as usize])
+        .map(|neigh| neigh.get(local))
+}
+
+/// whether the block at local coordinates `c` is opaque. `c` may lie in one of the face `neighbours`,
+/// blocks in unloaded neighbours and in segments that only share an edge or corner count as not opaque.
+fn opaque_at(opaque: &[u32], neighbours: &[Option<Vec<u32>>; 6], c: ICoords) -> bool {
+    let plane_size = Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.y };
+    // the offset of the segment containing `c`, and the coordinates within it
+    let (offset, local) = (c.l1_glob(), c.bl_loc());
+    let bitmap = if offset == ICoords::new(0, 0, 0) {
+        opaque
+    } else {
+        match Face::all().into_iter().find(|face| face.numeric() == offset).and_then(|face| neighbours[face as usize].as_ref()) {
+            Some(neigh) => neigh,
+            None => return false,
+        }
+    };
+    bitmap[plane_size.c1d(local.x, local.y)] & (1u32 << local.z) != 0
+}
+
+/// brightness of a face corner by the number of occluding blocks around it, 3 meaning none
+const AO_BRIGHTNESS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+/// ambient occlusion of the corner `corner` (see `BL_VERTICES`) of `face` from 0 (fully occluded) to 3 (not occluded).
+/// - `in_front`: the block in front of the face, the corner touches the blocks next to it in the plane of the face
+fn corner_occlusion(face: Face, corner: [u64; 3], in_front: ICoords, is_opaque: impl Fn(ICoords) -> bool) -> usize {
+    let normal = face.numeric();
+    // the directions from the face centre towards the corner along the two axes of the face
+    let towards = |axis: usize| if corner[axis] == 1 { 1 } else { -1 };
+    let tangents: Vec<ICoords> = [ICoords::new(towards(0), 0, 0), ICoords::new(0, towards(1), 0), ICoords::new(0, 0, towards(2))]
+        .into_iter()
+        .zip([normal.x, normal.y, normal.z])
+        .filter(|&(_, n)| n == 0)
+        .map(|(t, _)| t)
+        .collect();
+    let side1 = is_opaque(in_front + tangents[0]);
+    let side2 = is_opaque(in_front + tangents[1]);
+    if side1 && side2 {
+        return 0;
+    }
+    3 - side1 as usize - side2 as usize - is_opaque(in_front + tangents[0] + tangents[1]) as usize
+}
+
+/// the 4 corners of `face` as indices into `BL_VERTICES`, in the winding order of its triangles
+fn face_corners(face: Face) -> [usize; 4] {
+    let indices = face.indices().map(|idx| idx as usize);
+    let (first, second) = (&indices[..3], &indices[3..]);
+    // the two triangles share a diagonal, the remaining corner of each triangle lies opposite of it
+    let r = (0..3).find(|&i| !second.contains(&first[i])).unwrap();
+    let s = *second.iter().find(|c| !first.contains(c)).unwrap();
+    [first[r], first[(r + 1) % 3], s, first[(r + 2) % 3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::random::mt::Mt19937;
+    use crate::world::block::BlockType;
+    use crate::world::state::{BlockState, Half};
+    use super::*;
+
+    fn block(name: &str) -> BlockState {
+        BlockState::new(BlockType::from_name(name).unwrap())
+    }
+
+    fn uniform(block: BlockState) -> L1Segment {
+        let mut seg = L1Segment::default();
+        seg.fill(block);
+        seg
+    }
+
+    /// a segment in which each block is one of `blocks` or air, with the given probability of air
+    fn random_segment(seed: u32, blocks: &[BlockState], air: f32) -> L1Segment {
+        let mut rng = Mt19937::new(seed);
+        let mut seg = L1Segment::default();
+        for c in L1_SIZE_BL {
+            if rng.next_01() >= air {
+                seg.set(c, blocks[rng.next() as usize % blocks.len()]);
+            }
+        }
+        seg
+    }
+
+    /// the area of the triangles of each part of a mesh ([opaque, translucent]), by the direction of their normal
+    fn area_by_normal(parts: &[(Vec<ColoredVertex>, Vec<u32>); 2]) -> BTreeMap<(usize, [i32; 3]), f32> {
+        let mut areas = BTreeMap::new();
+        for (part, (vertices, indices)) in parts.iter().enumerate() {
+            for t in indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_slice(&vertices[t[i] as usize].pos[..3]));
+                let cross = (b - a).cross(c - a);
+                let normal = cross.normalize().to_array().map(|n| (n * 1000.).round() as i32);
+                *areas.entry((part, normal)).or_insert(0.) += cross.length() / 2.;
+            }
+        }
+        areas
+    }
+
+    fn assert_same_area(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], coloring: Coloring) {
+        let position = Vec3::new(-32., 64., 0.);
+        let naive = area_by_normal(&mesh_l1_segment(seg, neighbours, position, coloring));
+        let greedy = area_by_normal(&greedy_mesh_l1_segment(seg, neighbours, position, coloring));
+        assert_eq!(naive.keys().collect::<Vec<_>>(), greedy.keys().collect::<Vec<_>>());
+        for (key, area) in &naive {
+            assert!((area - greedy[key]).abs() < 1e-3, "area of {:?}: naive {}, greedy {}", key, area, greedy[key]);
+        }
+    }
+
+    const FLAT: Coloring = Coloring { face_shading: true, tint_seed: None };
+    const TINTED: Coloring = Coloring { face_shading: true, tint_seed: Some(7) };
+
+    #[test]
+    fn greedy_covers_the_same_area_as_naive() {
+        let stone = block("stone");
+        let air = uniform(BlockState::NO_BLOCK);
+        let terrain = random_segment(1, &[stone, block("dirt"), block("grass")], 0.5);
+        let shapes = random_segment(2, &[
+            stone,
+            block("glass"),
+            block("water"),
+            block("leaves"),
+            block("stone_slab"),
+            block("stone_slab").with_half(Half::Top),
+            block("stone_stairs").with_facing(Face::ZPos),
+            block("tall_grass"),
+            block("fence"),
+        ], 0.4);
+        let loaded = [Some(&terrain), Some(&air), Some(&shapes), Some(&terrain), Some(&air), Some(&shapes)];
+        let unloaded = [None; 6];
+
+        for seg in [uniform(stone), uniform(block("glass")), terrain.clone(), shapes.clone()] {
+            for neighbours in [loaded, unloaded, [Some(&air); 6]] {
+                for coloring in [FLAT, TINTED] {
+                    assert_same_area(&seg, neighbours, coloring);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_segment_is_one_quad_per_side() {
+        let stone = uniform(block("stone"));
+        let air = uniform(BlockState::NO_BLOCK);
+        let [(vertices, _), (translucent, _)] = greedy_mesh_l1_segment(&stone, [Some(&air); 6], Vec3::ZERO, FLAT);
+        assert_eq!(vertices.len(), 6 * 4);
+        assert!(translucent.is_empty());
+        let areas = area_by_normal(&mesh_l1_segment(&stone, [Some(&air); 6], Vec3::ZERO, FLAT));
+        assert_eq!(areas.len(), 6);
+        assert!(areas.values().all(|&a| a == (L1_SIZE_BL.x * L1_SIZE_BL.y) as f32));
+        // faces towards unloaded neighbours and towards covering neighbours are left out
+        let [(vertices, _), _] = greedy_mesh_l1_segment(&stone, [None, Some(&stone), None, None, None, None], Vec3::ZERO, FLAT);
+        assert!(vertices.is_empty());
+    }
+
+    /// fnv-1a hash of the bit patterns of the vertices and the indices of a mesh
+    fn mesh_hash(parts: &[(Vec<ColoredVertex>, Vec<u32>); 2]) -> u64 {
+        let mut words = Vec::new();
+        for (vertices, indices) in parts {
+            for v in vertices {
+                words.extend(v.pos.iter().chain(&v.color).chain(&v.normal).map(|f| f.to_bits()));
+            }
+            words.extend(indices);
+        }
+        words.iter().flat_map(|w| w.to_le_bytes()).fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3))
+    }
+
+    // hashes of the meshes in `tinted_meshes_are_reproducible`, only update them when the meshes are meant to change
+    const NAIVE_HASH: u64 = 0x3cbf_9414_8cd5_ed3f;
+    const GREEDY_HASH: u64 = 0x40c8_753e_d463_52e1;
+
+    type MeshFn = fn(&L1Segment, [Option<&L1Segment>; 6], Vec3, Coloring) -> [(Vec<ColoredVertex>, Vec<u32>); 2];
+
+    #[test]
+    fn tinted_meshes_are_reproducible() {
+        let coloring = Coloring { face_shading: true, tint_seed: Some(12345) };
+        // the tints are part of the format of exported meshes and must not depend on anything but the seed and the position
+        let tints = [ICoords::new(0, 0, 0), ICoords::new(-1, 5, 31), ICoords::new(100, -64, -7), ICoords::new(-2048, 2047, 3)]
+            .map(|c| coloring.tint(c));
+        assert_eq!(tints, [1, 0, 3, 2]);
+
+        let seg = random_segment(3, &[block("stone"), block("grass"), block("glass"), block("stone_slab")], 0.6);
+        let position = Vec3::new(-32., 0., 64.);
+        for (mesh, expected) in [(mesh_l1_segment as MeshFn, NAIVE_HASH), (greedy_mesh_l1_segment, GREEDY_HASH)] {
+            let first = mesh_hash(&mesh(&seg, [None; 6], position, coloring));
+            let second = mesh_hash(&mesh(&seg, [None; 6], position, coloring));
+            assert_eq!(first, second);
+            assert_eq!(first, expected, "{:#018x}", first);
+        }
+        // a different seed gives different tints
+        let other = Coloring { tint_seed: Some(54321), ..coloring };
+        assert_ne!(mesh_hash(&greedy_mesh_l1_segment(&seg, [None; 6], position, other)), GREEDY_HASH);
+    }
 }
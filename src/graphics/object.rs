@@ -0,0 +1,81 @@
+use crate::graphics::buffer::Buffer;
+use crate::graphics::meshing::{Mesh, SegmentMesh};
+use crate::graphics::vertex::Vertex;
+
+pub struct RawObject<'a> {
+    pub vertex_buffer: Buffer<'a>,
+    pub index_buffer: Buffer<'a>,
+    pub index_count: u32,
+    // the vertices are `BlockVertex`es that are drawn with the block pipeline, otherwise `ColoredVertex`es
+    pub textured: bool,
+}
+
+impl<'a> RawObject<'a> {
+    /// uploads the vertices and indices of `mesh`
+    ///
+    /// # Safety
+    /// the buffers are created on `device`, the object has to be freed before it is destroyed
+    pub unsafe fn new(device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties, mesh: &Mesh) -> Self {
+        unsafe fn vertex_buffer<'a, V: Vertex>(device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties,
+                                               vertices: &[V]) -> Buffer<'a> {
+            let vertex_buffer = Buffer::new_vertex::<V>(vertices.len(), device, device_memory_properties);
+            vertex_buffer.fill(vertices);
+            vertex_buffer
+        }
+
+        let (vertex_buffer, textured) = match mesh {
+            Mesh::Colored(vertices, _) => (vertex_buffer(device, device_memory_properties, vertices), false),
+            Mesh::Textured(vertices, _) => (vertex_buffer(device, device_memory_properties, vertices), true),
+        };
+        let indices = mesh.indices();
+        let index_buffer = Buffer::new_index(indices.len(), device, device_memory_properties);
+        index_buffer.fill(indices);
+
+        RawObject {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            textured,
+        }
+    }
+
+    /// frees the vertex and index buffer
+    ///
+    /// # Safety
+    /// the object must no longer be in use by the gpu
+    pub unsafe fn free(&self, device: &ash::Device) {
+        self.vertex_buffer.free(device);
+        self.index_buffer.free(device);
+    }
+}
+
+/// the graphics objects of an l1 segment. translucent faces are drawn in a separate pass after all opaque ones,
+/// so they get their own buffers.
+pub struct SegmentObjects<'a> {
+    pub opaque: Option<RawObject<'a>>,
+    pub translucent: Option<RawObject<'a>>,
+}
+
+impl<'a> SegmentObjects<'a> {
+    /// uploads the non-empty parts of `mesh`
+    ///
+    /// # Safety
+    /// the buffers are created on `device`, the objects have to be freed before it is destroyed
+    pub unsafe fn new(device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties, mesh: &SegmentMesh) -> Self {
+        let object = |mesh: &Mesh| (!mesh.is_empty()).then(|| RawObject::new(device, device_memory_properties, mesh));
+        SegmentObjects {
+            opaque: object(&mesh.opaque),
+            translucent: object(&mesh.translucent),
+        }
+    }
+
+    /// frees the buffers of both parts
+    ///
+    /// # Safety
+    /// the objects must no longer be in use by the gpu
+    pub unsafe fn free(&self, device: &ash::Device) {
+        for object in self.opaque.iter().chain(&self.translucent) {
+            object.free(device);
+        }
+    }
+}
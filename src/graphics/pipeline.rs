@@ -6,6 +6,8 @@ pub enum PipelineType {
     World,
     WorldLine,
     Hud,
+    // blended over the opaque world without writing depth, see `meshing::SegmentMesh`
+    Translucent,
 }
 
 // holds on to all necessary information for the pipeline to live
@@ -26,13 +28,19 @@ impl<'a> Pipeline<'a> {
                 PipelineType::World => rasterization_info_fill(),
                 PipelineType::WorldLine => rasterization_info_line(),
                 PipelineType::Hud => rasterization_info_fill(),
+                // the surface of water is also seen from below
+                PipelineType::Translucent => rasterization_info_double_sided(),
             },
             dynamic_state: dynamic_state_create_info(),
             input_assembly_state: input_assembly_state(),
             viewport_state: viewport_state_create_info(&scissors, &viewports),
             multisample_state: multisample_state_create_info(),
-            depth_stencil_state: depth_stencil_state_create_info(),
-            color_blend_state: color_blend_state_create_info(),
+            depth_stencil_state: depth_stencil_state_create_info(!matches!(p_type, PipelineType::Translucent)),
+            color_blend_state: match p_type {
+                PipelineType::World | PipelineType::WorldLine => vk::PipelineColorBlendStateCreateInfo::default().attachments(&OPAQUE_BLEND_ATTACHMENT_STATES),
+                PipelineType::Hud => color_blend_state_create_info(),
+                PipelineType::Translucent => vk::PipelineColorBlendStateCreateInfo::default().attachments(&TRANSLUCENT_BLEND_ATTACHMENT_STATES),
+            },
         }
     }
 
@@ -140,7 +148,8 @@ fn multisample_state_create_info() -> vk::PipelineMultisampleStateCreateInfo<'st
     }
 }
 
-fn depth_stencil_state_create_info() -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+// translucent faces are tested against the depth of the opaque world but must not hide each other
+fn depth_stencil_state_create_info(depth_write: bool) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
     let noop_stencil_state = vk::StencilOpState {
         fail_op: vk::StencilOp::KEEP,
         pass_op: vk::StencilOp::KEEP,
@@ -151,7 +160,7 @@ fn depth_stencil_state_create_info() -> vk::PipelineDepthStencilStateCreateInfo<
 
     vk::PipelineDepthStencilStateCreateInfo {
         depth_test_enable: vk::TRUE,
-        depth_write_enable: vk::TRUE,
+        depth_write_enable: depth_write as vk::Bool32,
         depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
         front: noop_stencil_state,
         back: noop_stencil_state,
@@ -171,6 +180,28 @@ static COLOR_BLEND_ATTACHMENT_STATES: [vk::PipelineColorBlendAttachmentState; 1]
     color_write_mask: vk::ColorComponentFlags::RGBA,
 }];
 
+static OPAQUE_BLEND_ATTACHMENT_STATES: [vk::PipelineColorBlendAttachmentState; 1] = [vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::FALSE,
+    src_color_blend_factor: vk::BlendFactor::ONE,
+    dst_color_blend_factor: vk::BlendFactor::ZERO,
+    color_blend_op: vk::BlendOp::ADD,
+    src_alpha_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+    alpha_blend_op: vk::BlendOp::ADD,
+    color_write_mask: vk::ColorComponentFlags::RGBA,
+}];
+
+static TRANSLUCENT_BLEND_ATTACHMENT_STATES: [vk::PipelineColorBlendAttachmentState; 1] = [vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::TRUE,
+    src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+    dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+    color_blend_op: vk::BlendOp::ADD,
+    src_alpha_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+    alpha_blend_op: vk::BlendOp::ADD,
+    color_write_mask: vk::ColorComponentFlags::RGBA,
+}];
+
 fn color_blend_state_create_info() -> vk::PipelineColorBlendStateCreateInfo<'static> {
     vk::PipelineColorBlendStateCreateInfo::default()
         .logic_op(vk::LogicOp::CLEAR)
@@ -187,6 +218,16 @@ fn rasterization_info_fill() -> vk::PipelineRasterizationStateCreateInfo<'static
     }
 }
 
+fn rasterization_info_double_sided() -> vk::PipelineRasterizationStateCreateInfo<'static> {
+    vk::PipelineRasterizationStateCreateInfo {
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        line_width: 1.0,
+        polygon_mode: vk::PolygonMode::FILL,
+        cull_mode: vk::CullModeFlags::NONE,
+        ..Default::default()
+    }
+}
+
 fn rasterization_info_line() -> vk::PipelineRasterizationStateCreateInfo<'static> {
     vk::PipelineRasterizationStateCreateInfo {
         front_face: vk::FrontFace::COUNTER_CLOCKWISE,
@@ -202,7 +243,7 @@ fn dynamic_state_create_info() -> vk::PipelineDynamicStateCreateInfo<'static> {
         .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
 }
 
-pub fn vertex_input_state<'a>(bindigs: &'a [vk::VertexInputBindingDescription; 1], attrs: &'a [vk::VertexInputAttributeDescription; 2])
+pub fn vertex_input_state<'a>(bindigs: &'a [vk::VertexInputBindingDescription; 1], attrs: &'a [vk::VertexInputAttributeDescription])
         -> vk::PipelineVertexInputStateCreateInfo<'a> {
     vk::PipelineVertexInputStateCreateInfo::default()
         .vertex_attribute_descriptions(attrs)
@@ -1,12 +1,18 @@
 use crate::world::{icoords::ICoords, size::Size3D};
 use super::camera::Camera;
 
-// set of l1segments that should be rendered based on camera
-pub fn scan(camera: &Camera) -> Vec<ICoords> {
-    let c0 = ICoords::new(camera.ray.origin.x as i64, camera.ray.origin.y as i64, camera.ray.origin.z as i64).l1_glob();
-    let diameter: i64 = 5;
-    let r = diameter / 2;
+// set of l1segments that should be rendered based on camera, nearest segments first
+// - `radius`: number of l1segments around the segment containing the camera in each direction
+pub fn scan(camera: &Camera, radius: i64) -> Vec<ICoords> {
+    let c0 = ICoords::from_vec3(camera.ray.origin).l1_glob();
+    let diameter = 2 * radius + 1;
     let cube = Size3D {x: diameter as u64, y: diameter as u64, z: diameter as u64};
-    let segs = cube.into_iter().map(|delta| {c0 + delta + ICoords::new(-r, -r, -r)}).collect();
-   segs
+    let mut segs: Vec<_> = cube.into_iter().map(|delta| {c0 + delta + ICoords::new(-radius, -radius, -radius)}).collect();
+    segs.sort_by_key(|&c| l1_distance(c, c0));
+    segs
+}
+
+// chebyshev distance between two l1segments, in l1segments
+pub fn l1_distance(a: ICoords, b: ICoords) -> i64 {
+    (a.x - b.x).abs().max((a.y - b.y).abs()).max((a.z - b.z).abs())
 }
@@ -4,6 +4,8 @@ use super::graphics_state::GraphicState;
 
 pub enum ShaderType {
     World,
+    // the world textured with the block atlas
+    Block,
     Hud,
 }
 
@@ -16,6 +18,8 @@ pub unsafe fn shader_module(g_state: &GraphicState, s_type: ShaderType, s_stage:
     let mut spv = match (s_type, s_stage) {
         (ShaderType::World, ShaderStage::Fragment) => std::io::Cursor::new(&include_bytes!("../shaders/frag.spv")[..]),
         (ShaderType::World, ShaderStage::Vertex) => std::io::Cursor::new(&include_bytes!("../shaders/vert.spv")[..]),
+        (ShaderType::Block, ShaderStage::Fragment) => std::io::Cursor::new(&include_bytes!("../shaders/block_frag.spv")[..]),
+        (ShaderType::Block, ShaderStage::Vertex) => std::io::Cursor::new(&include_bytes!("../shaders/block_vert.spv")[..]),
         (ShaderType::Hud, ShaderStage::Fragment) => std::io::Cursor::new(&include_bytes!("../shaders/hud_frag.spv")[..]),
         (ShaderType::Hud, ShaderStage::Vertex) => std::io::Cursor::new(&include_bytes!("../shaders/hud_vert.spv")[..]),
     };
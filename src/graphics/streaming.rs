@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use crate::config;
+use crate::jobs::*;
+use crate::profiler::*;
+use crate::world::{World, Face, icoords::ICoords, segment::L1_SIZE_BL};
+use super::camera::Camera;
+use super::scanner::{self, l1_distance};
+use super::world_renderer::WorldRenderer;
+
+/// loads, meshes and unloads l1 segments around the camera.
+/// generation and meshing run on a `WorkerPool`, only the upload of finished meshes happens on the render thread.
+pub struct Streamer {
+    /// radius in l1 segments around the camera in which segments are rendered
+    pub render_distance: i64,
+    /// number of l1 segments that a segment has to be beyond `render_distance` before its graphics object is freed.
+    /// this avoids freeing and re-meshing segments when the camera moves back and forth across a segment border.
+    pub hysteresis: i64,
+    /// maximum number of jobs in flight
+    pub budget: usize,
+    pool: WorkerPool,
+    // segments whose current mesh has been uploaded (possibly without a graphics object if the mesh is empty)
+    meshed: HashSet<ICoords>,
+}
+
+impl Streamer {
+    pub fn new(render_distance: i64) -> Self {
+        Streamer {
+            render_distance,
+            hysteresis: config::STREAMING_HYSTERESIS,
+            budget: config::STREAMING_BUDGET,
+            pool: WorkerPool::with_available_parallelism(),
+            meshed: HashSet::new(),
+        }
+    }
+
+    pub fn set_render_distance(&mut self, render_distance: i64) {
+        self.render_distance = render_distance.max(0);
+    }
+
+    fn mesh_job(world: &World, l1_glob: ICoords) -> Option<Job> {
+        let offset = l1_glob * L1_SIZE_BL.into();
+        let segment = world.l1_segment(offset)?.clone();
+        let neighbours = Face::all().map(|face| world.l1_segment(offset + face.numeric() * L1_SIZE_BL.into()).cloned());
+        Some(Job::Mesh { l1_glob, segment, neighbours: Box::new(neighbours), mesher: world.mesher() })
+    }
+
+    /// diffs the segments around the camera against the loaded ones, queues generation and meshing of new segments,
+    /// uploads finished meshes and frees the graphics objects of segments that are out of range.
+    ///
+    /// # Safety
+    /// no graphics object of `renderer` may be in use by the gpu
+    pub unsafe fn update<'a>(&mut self, world: &mut World, renderer: &mut WorldRenderer<'a>, camera: &Camera,
+                            device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) {
+        p_start("streamer.update");
+        let center = ICoords::from_vec3(camera.ray.origin).l1_glob();
+        // adopt graphics objects that were created outside of the streamer
+        self.meshed.extend(renderer.objects.keys());
+
+        // unload everything that moved out of range
+        let max_distance = self.render_distance + self.hysteresis;
+        let out_of_range: Vec<_> = renderer.objects.keys().chain(self.meshed.iter())
+            .filter(|&&c| l1_distance(c, center) > max_distance)
+            .copied()
+            .collect();
+        for l1_glob in out_of_range {
+            renderer.free_graphics_object(l1_glob, device);
+            self.meshed.remove(&l1_glob);
+        }
+        for l1_glob in self.pool.pending_segments() {
+            if l1_distance(l1_glob, center) > max_distance {
+                self.pool.cancel(l1_glob);
+            }
+        }
+
+        p_start("streamer.upload");
+        for result in self.pool.finished() {
+            match result {
+                JobResult::Generated { l1_glob, segment } => {
+                    // a segment edited in the meantime is kept, nothing changed then
+                    let Some(relit) = world.insert_l1_segment(l1_glob * L1_SIZE_BL.into(), segment) else {
+                        continue;
+                    };
+                    // the faces of meshed neighbours towards the new segment may be covered now,
+                    // and light spreading from or blocked by the new segment changes the colours of nearby segments
+                    let neighbours = Face::all().map(|face| l1_glob + face.numeric());
+                    for neighbour in relit.into_iter().chain(neighbours).collect::<HashSet<_>>() {
+                        if self.meshed.contains(&neighbour) {
+                            if let Some(job) = Self::mesh_job(world, neighbour) {
+                                self.pool.submit(job);
+                            }
+                        }
+                    }
+                },
+                JobResult::Meshed { l1_glob, mesh } => {
+                    renderer.upload_graphics_object(l1_glob, &mesh, device, device_memory_properties);
+                    self.meshed.insert(l1_glob);
+                },
+            }
+        }
+        p_end("streamer.upload");
+
+        // a new mesher replaces every mesh in the background, the old meshes stay visible until then.
+        // pending mesh jobs still use the old mesher and are superseded as well.
+        if world.take_mesher_changed() {
+            let pending = self.pool.pending_segments().into_iter().filter(|&c| self.pool.is_pending(c, JobKind::Mesh));
+            for l1_glob in self.meshed.iter().copied().chain(pending).collect::<HashSet<_>>() {
+                if let Some(job) = Self::mesh_job(world, l1_glob) {
+                    self.pool.submit(job);
+                }
+            }
+        }
+
+        for l1_glob in scanner::scan(camera, self.render_distance) {
+            if self.pool.n_pending() >= self.budget {
+                break;
+            }
+            if self.meshed.contains(&l1_glob) || self.pool.is_pending(l1_glob, JobKind::Generate) || self.pool.is_pending(l1_glob, JobKind::Mesh) {
+                continue;
+            }
+
+            match Self::mesh_job(world, l1_glob) {
+                Some(job) => self.pool.submit(job),
+                None => self.pool.submit(Job::Generate { l1_glob, seed: world.seed() }),
+            }
+        }
+        p_end("streamer.update");
+    }
+
+    /// synchronously re-meshes the segments in which blocks have changed, so that edits are visible in the next frame.
+    /// segments that have not been meshed yet are left to `update`, as is remeshing after the mesher changed.
+    ///
+    /// # Safety
+    /// no graphics object of `renderer` may be in use by the gpu
+    pub unsafe fn remesh_dirty<'a>(&mut self, world: &mut World, renderer: &mut WorldRenderer<'a>,
+                                  device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) {
+        p_start("streamer.remesh_dirty");
+        for l1_glob in world.take_dirty() {
+            if !self.meshed.contains(&l1_glob) {
+                continue;
+            }
+            // a mesh that is being built in the background would be outdated
+            self.pool.cancel(l1_glob);
+            renderer.update_graphics_object(world, l1_glob, device, device_memory_properties);
+        }
+        p_end("streamer.remesh_dirty");
+    }
+}
@@ -0,0 +1,153 @@
+// smooth meshing with surface nets
+//
+// the density is sampled at the centre of every block. every cell between 8 neighbouring samples that the surface
+// passes through gets one vertex, placed at the average of the points where the surface crosses the edges of the cell.
+// every edge between two samples on different sides of the surface gets a quad connecting the vertices of the 4 cells
+// around the edge.
+//
+// a segment owns the edges that start at one of its own samples, so it needs the samples of one block layer of its
+// neighbours on each side. the cells at the border are computed by both
+// neighbouring segments from the same samples, which makes the meshes of neighbouring segments stitch seamlessly.
+
+use std::sync::Arc;
+use glam::Vec3;
+use crate::config;
+use crate::profiler::*;
+use crate::graphics::{meshing::{block_at, Mesher, SegmentMesh}, vertex::ColoredVertex};
+use crate::world::{
+    generation,
+    icoords::ICoords,
+    segment::{L1Segment, L1_SIZE_BL},
+    size::Size3D,
+};
+
+/// density of a block that was placed or removed by an edit, where the terrain density has the other sign
+const EDIT_DENSITY: f32 = 0.25;
+/// samples per axis, from one block before the segment to one block after it
+const SAMPLES: Size3D = Size3D { x: L1_SIZE_BL.x + 2, y: L1_SIZE_BL.y + 2, z: L1_SIZE_BL.z + 2 };
+/// cells per axis, the minimum corner of a cell goes from one block before the segment to the last block of the segment
+const CELLS: Size3D = Size3D { x: L1_SIZE_BL.x + 1, y: L1_SIZE_BL.y + 1, z: L1_SIZE_BL.z + 1 };
+/// colour of the surface where no solid block is loaded next to it
+const DEFAULT_COLOR: [f32; 4] = [0.35, 0.65, 0.25, 1.0];
+/// direction the light that shades the surface comes from
+const SUN: Vec3 = Vec3::new(0.3, 0.9, 0.3);
+
+/// smooth terrain following the density of `generation::density`. blocks that differ from the generated terrain
+/// are taken into account as long as they are in the segment or one of its face neighbours.
+pub struct SmoothMesher {
+    /// seed of the world whose terrain is meshed
+    pub seed: u32,
+}
+
+impl Default for SmoothMesher {
+    fn default() -> Self {
+        SmoothMesher { seed: config::WORLD_SEED }
+    }
+}
+
+impl Mesher for SmoothMesher {
+    fn name(&self) -> &'static str {
+        "smooth"
+    }
+
+    fn mesh(&self, seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3) -> SegmentMesh {
+        // the surface follows the density and has no translucent faces
+        [mesh_l1_segment(seg, neighbours, position, self.seed), (Vec::new(), Vec::new())].into()
+    }
+
+    fn for_seed(&self, seed: u32) -> Option<Arc<dyn Mesher>> {
+        Some(Arc::new(SmoothMesher { seed }))
+    }
+}
+
+/// the density at local block coordinates `c`, which may lie outside of the segment
+fn sample(seg: &L1Segment, neighbours: &[Option<&L1Segment>; 6], seed: u32, origin: ICoords, c: ICoords) -> f32 {
+    let density = generation::density(seed, origin + c);
+    match block_at(seg, neighbours, c) {
+        Some(block) if block.is_solid() && density <= 0. => EDIT_DENSITY,
+        Some(block) if !block.is_solid() && density > 0. => -EDIT_DENSITY,
+        _ => density,
+    }
+}
+
+/// - `neighbours`: [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
+/// - `position`: global coordinates of the 0 0 0 block of the segment
+/// - `seed`: seed of the world the segment was generated in
+pub fn mesh_l1_segment(seg: &L1Segment, neighbours: [Option<&L1Segment>; 6], position: Vec3, seed: u32) -> (Vec<ColoredVertex>, Vec<u32>) {
+    let origin = ICoords::from_vec3(position);
+    let one = ICoords::new(1, 1, 1);
+
+    p_start("surface_nets.sample");
+    // samples are indexed from -1, so sample `c` is at `SAMPLES.c1d(c + one)`
+    let samples: Vec<f32> = SAMPLES.into_iter().map(|i| sample(seg, &neighbours, seed, origin, i - one)).collect();
+    let density = |c: ICoords| samples[SAMPLES.c1d(c + one) as usize];
+    p_end("surface_nets.sample");
+
+    p_start("surface_nets.place_vertices");
+    let corners: Vec<ICoords> = (Size3D { x: 2, y: 2, z: 2 }).into_iter().collect();
+    let mut cell_vertices = vec![u32::MAX; CELLS.volume() as usize];
+    let mut vertices = Vec::<ColoredVertex>::new();
+    for i in CELLS {
+        let cell = i - one;
+        let d = corners.iter().map(|&corner| density(cell + corner)).collect::<Vec<_>>();
+        if d.iter().all(|&d| d > 0.) || d.iter().all(|&d| d <= 0.) {
+            continue;
+        }
+
+        let (mut sum, mut n) = (Vec3::ZERO, 0);
+        let mut gradient = Vec3::ZERO;
+        for (a, &ca) in corners.iter().enumerate() {
+            for (b, &cb) in corners.iter().enumerate().skip(a + 1) {
+                // only the 12 edges of the cell, whose corners differ along one axis
+                let diff = cb - ca;
+                if diff.x + diff.y + diff.z != 1 {
+                    continue;
+                }
+                gradient += diff.vec3() * (d[b] - d[a]);
+                if (d[a] > 0.) != (d[b] > 0.) {
+                    let t = d[a] / (d[a] - d[b]);
+                    sum += ca.vec3() + diff.vec3() * t;
+                    n += 1;
+                }
+            }
+        }
+        // the density grows towards the inside, the normal points outside
+        let normal = -gradient.normalize_or_zero();
+        // samples are at the centres of the blocks
+        let pos = position + cell.vec3() + Vec3::splat(0.5) + sum / n as f32;
+        let shade = 0.5 + 0.5 * normal.dot(SUN.normalize()).max(0.);
+        // the colour of a solid block at the corners, blocks in unloaded segments follow the generated terrain
+        let [r, g, b, a] = corners.iter()
+            .filter_map(|&corner| block_at(seg, &neighbours, cell + corner))
+            .find(|block| block.is_solid())
+            .and_then(|block| block.block.color())
+            .unwrap_or(DEFAULT_COLOR);
+        cell_vertices[CELLS.c1d(i) as usize] = vertices.len() as u32;
+        vertices.push(ColoredVertex {
+            pos: [pos.x, pos.y, pos.z, 1.0],
+            color: [r * shade, g * shade, b * shade, a],
+            normal: normal.to_array(),
+        });
+    }
+    p_end("surface_nets.place_vertices");
+
+    p_start("surface_nets.connect_vertices");
+    let mut indices = Vec::<u32>::new();
+    let axes = [ICoords::new(1, 0, 0), ICoords::new(0, 1, 0), ICoords::new(0, 0, 1)];
+    for c in L1_SIZE_BL {
+        let inside = density(c) > 0.;
+        for a in 0..3 {
+            if inside == (density(c + axes[a]) > 0.) {
+                continue;
+            }
+            let (u, v) = (axes[(a + 1) % 3], axes[(a + 2) % 3]);
+            // the winding makes the quad face away from the inside, like the faces of the block meshers
+            let quad = if inside { [c, c - v, c - u - v, c - u] } else { [c, c - u, c - u - v, c - v] };
+            let quad = quad.map(|cell| cell_vertices[CELLS.c1d(cell + one) as usize]);
+            indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+        }
+    }
+    p_end("surface_nets.connect_vertices");
+
+    (vertices, indices)
+}
@@ -9,10 +9,10 @@ pub struct Sampler {
 }
 
 impl Sampler {
-    unsafe fn create(g_state: &GraphicState) -> Self {
+    unsafe fn create(g_state: &GraphicState, filter: vk::Filter) -> Self {
         let sampler_create_info = vk::SamplerCreateInfo {
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
+            mag_filter: filter,
+            min_filter: filter,
             address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
             address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
             address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
@@ -233,6 +233,18 @@ impl Texture {
         let img: image::ImageBuffer<image::Rgba<u8>, &[u8]> = image::ImageBuffer::from_raw(width, height, bytes).expect("couldnt read raw image");
         img.save("target/texture.png").expect("could not save image");
 
+        Self::create(g_state, bytes, width, height, vk::Filter::LINEAR)
+    }
+
+    /// a texture that is sampled without interpolation, for pixel art like the block textures
+    ///
+    /// # Safety
+    /// the texture is created on the device of `g_state` and has to be freed before it is destroyed
+    pub unsafe fn create_pixelated(g_state: &GraphicState, bytes: &[u8], width: u32, height: u32) -> Self {
+        Self::create(g_state, bytes, width, height, vk::Filter::NEAREST)
+    }
+
+    unsafe fn create(g_state: &GraphicState, bytes: &[u8], width: u32, height: u32, filter: vk::Filter) -> Self {
         let buffer_size: vk::DeviceSize = 4 * (width as u64) * (height as u64);
 
         let buffer = buffer::Buffer::new(
@@ -259,7 +271,7 @@ impl Texture {
         buffer.free(&g_state.device);
 
         let image_view = ImageView::create(g_state, &image);
-        let sampler = Sampler::create(g_state);
+        let sampler = Sampler::create(g_state, filter);
 
         Texture {
             image,
@@ -1,11 +1,17 @@
+// the vertex types are plain data that the meshers produce without vulkan,
+// only their input descriptions for the pipelines need the `graphics` feature
+#[cfg(feature = "graphics")]
 use std::mem;
+#[cfg(feature = "graphics")]
 use ash::vk;
+#[cfg(feature = "graphics")]
 use crate::offset_of;
 
 // Clone, Copy, and Default are "supertraits" of Vertex
+#[cfg(feature = "graphics")]
 pub trait Vertex: Clone + Copy + Default {
     fn binding_description<'a>() -> [vk::VertexInputBindingDescription; 1];
-    fn attribute_desctiptions<'a>() -> [vk::VertexInputAttributeDescription; 2];
+    fn attribute_desctiptions<'a>() -> Vec<vk::VertexInputAttributeDescription>;
 }
 
 // use the C representation because the Default rust representation may reorder fields
@@ -15,8 +21,11 @@ pub trait Vertex: Clone + Copy + Default {
 pub struct ColoredVertex {
     pub pos: [f32; 4],
     pub color: [f32; 4],
+    // the shaders do not use the normal yet, shading is baked into the colour by the meshers
+    pub normal: [f32; 3],
 }
 
+#[cfg(feature = "graphics")]
 impl Vertex for ColoredVertex {
     fn binding_description() -> [vk::VertexInputBindingDescription; 1] {
         [vk::VertexInputBindingDescription {
@@ -26,8 +35,8 @@ impl Vertex for ColoredVertex {
         }]
     }
 
-    fn attribute_desctiptions() -> [vk::VertexInputAttributeDescription; 2] {
-        [vk::VertexInputAttributeDescription {
+    fn attribute_desctiptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![vk::VertexInputAttributeDescription {
             location: 0,
             binding: 0,
             format: vk::Format::R32G32B32A32_SFLOAT,
@@ -57,6 +66,7 @@ impl TexturedVertex {
     };
 }
 
+#[cfg(feature = "graphics")]
 impl Vertex for TexturedVertex {
     fn binding_description() -> [vk::VertexInputBindingDescription; 1] {
         [vk::VertexInputBindingDescription {
@@ -66,8 +76,8 @@ impl Vertex for TexturedVertex {
         }]
     }
 
-    fn attribute_desctiptions() -> [vk::VertexInputAttributeDescription; 2] {
-        [vk::VertexInputAttributeDescription {
+    fn attribute_desctiptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![vk::VertexInputAttributeDescription {
             location: 0,
             binding: 0,
             format: vk::Format::R32G32B32A32_SFLOAT,
@@ -81,3 +91,54 @@ impl Vertex for TexturedVertex {
         }]
     }
 }
+
+// a vertex of a textured block face
+#[repr(C)]
+#[derive(Clone, Debug, Copy, Default)]
+pub struct BlockVertex {
+    pub pos: [f32; 4],
+    // coordinates in the block texture atlas
+    pub tex_coord: [f32; 2],
+    // light and ambient occlusion, multiplied with the texture colour
+    pub shade: f32,
+    // the `Face` the vertex belongs to, the block shader does not use it yet
+    pub face: u32,
+}
+
+#[cfg(feature = "graphics")]
+impl Vertex for BlockVertex {
+    fn binding_description() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: mem::size_of::<BlockVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    fn attribute_desctiptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: offset_of!(BlockVertex, pos) as u32,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: offset_of!(BlockVertex, tex_coord) as u32,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32_SFLOAT,
+            offset: offset_of!(BlockVertex, shade) as u32,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 3,
+            binding: 0,
+            format: vk::Format::R32_UINT,
+            offset: offset_of!(BlockVertex, face) as u32,
+        }]
+    }
+}
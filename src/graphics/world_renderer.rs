@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use crate::world::{World, icoords::ICoords, segment::L1_SIZE_BL};
+use super::meshing::SegmentMesh;
+use super::object::{RawObject, SegmentObjects};
+
+/// the graphics objects of the l1 segments of a `World`. the world only holds the terrain,
+/// the renderer turns its meshes into gpu buffers and keeps them until they are freed.
+#[derive(Default)]
+pub struct WorldRenderer<'a> {
+    /// graphics objects of the l1 segments, indexed by the global coordinates of the segment (see `ICoords::l1_glob`)
+    pub objects: HashMap<ICoords, SegmentObjects<'a>>,
+}
+
+impl<'a> WorldRenderer<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// creates the graphics objects of all loaded l1 segments of `world`, e.g. after `World::load`
+    ///
+    /// # Safety
+    /// the objects are created on `device`, which has to outlive the renderer
+    pub unsafe fn generate_graphics_objects(&mut self, world: &World, device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) {
+        for l1_glob in world.l1_segment_coords() {
+            self.update_graphics_object(world, l1_glob, device, device_memory_properties);
+        }
+    }
+
+    /// (re-)creates the graphics object of the l1 segment of `world` at global segment coordinates `l1_glob`
+    ///
+    /// # Safety
+    /// the previous object of the segment is freed, it must no longer be in use by the gpu
+    pub unsafe fn update_graphics_object(&mut self, world: &World, l1_glob: ICoords, device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) {
+        match world.mesh_l1_segment(l1_glob) {
+            Some(mesh) => self.upload_graphics_object(l1_glob, &mesh, device, device_memory_properties),
+            None => self.free_graphics_object(l1_glob, device),
+        }
+    }
+
+    /// replaces the graphics object of the l1 segment at global segment coordinates `l1_glob` with a mesh that has been built elsewhere
+    ///
+    /// # Safety
+    /// the previous object of the segment is freed, it must no longer be in use by the gpu
+    pub unsafe fn upload_graphics_object(&mut self, l1_glob: ICoords, mesh: &SegmentMesh,
+                                        device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) {
+        self.free_graphics_object(l1_glob, device);
+        if mesh.n_indices() > 0 {
+            self.objects.insert(l1_glob, SegmentObjects::new(device, device_memory_properties, mesh));
+        }
+    }
+
+    /// the opaque graphics objects in no particular order
+    pub fn opaque_objects(&self) -> impl Iterator<Item = &RawObject<'a>> {
+        self.objects.values().filter_map(|o| o.opaque.as_ref())
+    }
+
+    /// the translucent graphics objects from the segment farthest from `eye` to the nearest one, the order in which they are blended
+    pub fn translucent_objects_back_to_front(&self, eye: glam::Vec3) -> Vec<&RawObject<'a>> {
+        let size: ICoords = L1_SIZE_BL.into();
+        let mut objects: Vec<_> = self.objects.iter()
+            .filter_map(|(&l1_glob, o)| o.translucent.as_ref().map(|t| (((l1_glob * size).vec3() + size.vec3() / 2.).distance_squared(eye), t)))
+            .collect();
+        objects.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        objects.into_iter().map(|(_, o)| o).collect()
+    }
+
+    /// frees the graphics object of the l1 segment at global segment coordinates `l1_glob`, if there is one
+    ///
+    /// # Safety
+    /// the object must no longer be in use by the gpu
+    pub unsafe fn free_graphics_object(&mut self, l1_glob: ICoords, device: &ash::Device) {
+        if let Some(o) = self.objects.remove(&l1_glob) {
+            o.free(device);
+        }
+    }
+
+    /// frees all graphics objects
+    ///
+    /// # Safety
+    /// the objects must no longer be in use by the gpu
+    pub unsafe fn free(&mut self, device: &ash::Device) {
+        for (_, o) in self.objects.drain() {
+            o.free(device);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use crate::graphics::meshing::{Mesher, SegmentMesh};
+use crate::world::{generation, icoords::ICoords, segment::{L1Segment, L1_SIZE_BL}};
+
+/// work that can be done off the render thread. segments are identified by their global l1 coordinates.
+pub enum Job {
+    Generate {
+        l1_glob: ICoords,
+        /// seed of the world the segment belongs to
+        seed: u32,
+    },
+    Mesh {
+        l1_glob: ICoords,
+        segment: L1Segment,
+        /// [XPos, XNeg, YPos, YNeg, ZPos, ZNeg]
+        neighbours: Box<[Option<L1Segment>; 6]>,
+        mesher: Arc<dyn Mesher>,
+    },
+}
+
+pub enum JobResult {
+    Generated {
+        l1_glob: ICoords,
+        segment: L1Segment,
+    },
+    Meshed {
+        l1_glob: ICoords,
+        mesh: SegmentMesh,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum JobKind {
+    Generate,
+    Mesh,
+}
+
+impl Job {
+    pub fn l1_glob(&self) -> ICoords {
+        match self {
+            Job::Generate { l1_glob, .. } => *l1_glob,
+            Job::Mesh { l1_glob, .. } => *l1_glob,
+        }
+    }
+
+    pub fn kind(&self) -> JobKind {
+        match self {
+            Job::Generate { .. } => JobKind::Generate,
+            Job::Mesh { .. } => JobKind::Mesh,
+        }
+    }
+
+    fn run(self) -> JobResult {
+        match self {
+            Job::Generate { l1_glob, seed } => JobResult::Generated {
+                l1_glob,
+                segment: generation::generate_l1_segment(seed, l1_glob * L1_SIZE_BL.into()),
+            },
+            Job::Mesh { l1_glob, segment, neighbours, mesher } => {
+                let offset = l1_glob * L1_SIZE_BL.into();
+                let mesh = mesher.mesh(&segment, neighbours.each_ref().map(Option::as_ref), offset.vec3());
+                JobResult::Meshed { l1_glob, mesh }
+            }
+        }
+    }
+}
+
+struct Ticket {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// a fixed number of worker threads that process `Job`s in submission order
+pub struct WorkerPool {
+    sender: Option<Sender<(u64, Arc<AtomicBool>, Job)>>,
+    results: Receiver<(u64, JobResult)>,
+    workers: Vec<JoinHandle<()>>,
+    // the latest submitted job of each kind per segment, results of older jobs are discarded
+    pending: HashMap<(ICoords, JobKind), Ticket>,
+    next_id: u64,
+}
+
+impl WorkerPool {
+    pub fn new(n_workers: usize) -> Self {
+        let (sender, jobs) = mpsc::channel::<(u64, Arc<AtomicBool>, Job)>();
+        let (results_sender, results) = mpsc::channel();
+        let jobs = Arc::new(Mutex::new(jobs));
+
+        let workers = (0..n_workers.max(1)).map(|i| {
+            let jobs = Arc::clone(&jobs);
+            let results_sender = results_sender.clone();
+            thread::Builder::new().name(format!("worker-{}", i)).spawn(move || loop {
+                // the lock is released as soon as a job has been received
+                let message = jobs.lock().unwrap().recv();
+                let Ok((id, cancelled, job)) = message else {
+                    // the pool has been dropped
+                    break;
+                };
+                if cancelled.load(Ordering::Relaxed) {
+                    continue;
+                }
+                if results_sender.send((id, job.run())).is_err() {
+                    break;
+                }
+            }).expect("unable to spawn worker thread")
+        }).collect();
+
+        WorkerPool {
+            sender: Some(sender),
+            results,
+            workers,
+            pending: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// a pool with one worker per available core, leaving one core for the render thread
+    pub fn with_available_parallelism() -> Self {
+        let n = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(n.saturating_sub(1))
+    }
+
+    /// queues `job`. a pending job of the same kind for the same segment is superseded.
+    pub fn submit(&mut self, job: Job) {
+        let key = (job.l1_glob(), job.kind());
+        if let Some(old) = self.pending.remove(&key) {
+            old.cancelled.store(true, Ordering::Relaxed);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.pending.insert(key, Ticket { id, cancelled: Arc::clone(&cancelled) });
+        self.sender.as_ref().unwrap().send((id, cancelled, job)).expect("all workers have stopped");
+    }
+
+    /// cancels all pending jobs for the segment at `l1_glob`. their results will not be returned.
+    pub fn cancel(&mut self, l1_glob: ICoords) {
+        for kind in [JobKind::Generate, JobKind::Mesh] {
+            if let Some(ticket) = self.pending.remove(&(l1_glob, kind)) {
+                ticket.cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn is_pending(&self, l1_glob: ICoords, kind: JobKind) -> bool {
+        self.pending.contains_key(&(l1_glob, kind))
+    }
+
+    /// segments with at least one pending job
+    pub fn pending_segments(&self) -> Vec<ICoords> {
+        self.pending.keys().map(|&(l1_glob, _)| l1_glob).collect()
+    }
+
+    pub fn n_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// returns all finished jobs without blocking, skipping cancelled and superseded ones
+    pub fn finished(&mut self) -> Vec<JobResult> {
+        let mut finished = Vec::new();
+        loop {
+            match self.results.try_recv() {
+                Ok((id, result)) => {
+                    let key = match &result {
+                        JobResult::Generated { l1_glob, .. } => (*l1_glob, JobKind::Generate),
+                        JobResult::Meshed { l1_glob, .. } => (*l1_glob, JobKind::Mesh),
+                    };
+                    if self.pending.get(&key).is_some_and(|ticket| ticket.id == id) {
+                        self.pending.remove(&key);
+                        finished.push(result);
+                    }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => panic!("all workers have stopped"),
+            }
+        }
+        finished
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        for ticket in self.pending.values() {
+            ticket.cancelled.store(true, Ordering::Relaxed);
+        }
+        // closing the channel stops the workers once they are idle
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
@@ -1,28 +1,52 @@
+#[cfg(feature = "graphics")]
 use ash::vk;
+#[cfg(feature = "graphics")]
 use std::borrow::Cow;
-use std::default::Default;
+#[cfg(feature = "graphics")]
 use std::ffi::CStr;
 
+#[cfg(feature = "graphics")]
 pub mod controls;
+// meshing and export work on the cpu and are always built,
+// everything that needs vulkan or a window is behind the `graphics` feature
 pub mod graphics {
+    pub mod meshing;
+    pub mod atlas;
+    pub mod export;
+    pub mod surface_nets;
+    pub mod vertex;
+    pub mod geometry;
+    #[cfg(feature = "graphics")]
     pub mod camera;
+    #[cfg(feature = "graphics")]
     pub mod shader;
+    #[cfg(feature = "graphics")]
     pub mod graphics_object;
+    #[cfg(feature = "graphics")]
     pub mod graphics_state;
-    pub mod meshing;
+    #[cfg(feature = "graphics")]
     pub mod pipeline;
+    #[cfg(feature = "graphics")]
     pub mod scanner;
+    #[cfg(feature = "graphics")]
+    pub mod streaming;
+    #[cfg(feature = "graphics")]
     pub mod buffer;
+    #[cfg(feature = "graphics")]
     pub mod texture;
-    pub mod vertex;
-    pub mod geometry;
+    #[cfg(feature = "graphics")]
+    pub mod object;
+    #[cfg(feature = "graphics")]
+    pub mod world_renderer;
 }
 pub mod random {
     pub mod mt;
 }
 pub mod profiler;
+pub mod jobs;
 pub mod world;
 pub mod config;
+#[cfg(feature = "graphics")]
 pub mod ui {
     pub mod text;
     pub mod font;
@@ -43,6 +67,7 @@ macro_rules! offset_of {
     }};
 }
 
+#[cfg(feature = "graphics")]
 unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -76,6 +101,7 @@ unsafe extern "system" fn vulkan_debug_callback(
     vk::FALSE
 }
 
+#[cfg(feature = "graphics")]
 pub fn find_memorytype_index(
     memory_prop: &vk::PhysicalDeviceMemoryProperties,
     memory_req: &vk::MemoryRequirements,
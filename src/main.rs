@@ -1,5 +1,6 @@
 use std::default::Default;
 use std::mem;
+use std::path::Path;
 use std::time;
 use std::time::Duration;
 use citrus::random;
@@ -14,15 +15,19 @@ use citrus::{
     controls::*,
     world::{
         *,
-        block::*,
+        registry,
     },
     graphics::{
+        atlas,
         shader::*,
         graphics_object::*,
         vertex::*,
         buffer::*,
         camera::*,
         graphics_state::*,
+        streaming::*,
+        world_renderer::WorldRenderer,
+        object::RawObject,
         texture::*,
         geometry::*,
         pipeline::*,
@@ -70,7 +75,15 @@ unsafe fn create_descriptor_set_layout(device: &ash::Device) -> ash::vk::Descrip
         ..Default::default()
     };
 
-    let bindings = [ubo_layout_binding, hud_ubo_layout_binding, sampler_layout_binding];
+    let atlas_layout_binding = vk::DescriptorSetLayoutBinding {
+        binding: 3,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        ..Default::default()
+    };
+
+    let bindings = [ubo_layout_binding, hud_ubo_layout_binding, sampler_layout_binding, atlas_layout_binding];
 
     let layout_info = vk::DescriptorSetLayoutCreateInfo {
         binding_count: bindings.len() as u32,
@@ -90,7 +103,7 @@ unsafe fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
 
     let sampler_pool_size = vk::DescriptorPoolSize {
         ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-        descriptor_count: 1,
+        descriptor_count: 2,
         ..Default::default()
     };
 
@@ -107,9 +120,9 @@ unsafe fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
     descriptor_pool
 }
 
-// allocate a new descriptor set for a texture sampler and a uniform buffer object
+// allocate a new descriptor set for the texture samplers and the uniform buffer objects
 unsafe fn create_descriptor_sets(device: &ash::Device, pool: vk::DescriptorPool, layout: vk::DescriptorSetLayout, 
-                            uni_buffer: vk::Buffer, hud_uni_buffer: vk::Buffer, texture: &Texture) -> Vec<vk::DescriptorSet> {
+                            uni_buffer: vk::Buffer, hud_uni_buffer: vk::Buffer, texture: &Texture, atlas: &Texture) -> Vec<vk::DescriptorSet> {
     let alloc_info = vk::DescriptorSetAllocateInfo {
         descriptor_pool: pool,
         descriptor_set_count: 1,
@@ -170,7 +183,23 @@ unsafe fn create_descriptor_sets(device: &ash::Device, pool: vk::DescriptorPool,
         ..Default::default()
     };
 
-    device.update_descriptor_sets(&[descriptor_write, hud_descriptor_write, sampler_descriptor_write], &[]);
+    let atlas_info = vk::DescriptorImageInfo {
+        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        image_view: atlas.image_view.vk_image_view,
+        sampler: atlas.sampler.vk_sampler,
+    };
+
+    let atlas_descriptor_write = vk::WriteDescriptorSet {
+        dst_set: descriptor_sets[0],
+        dst_binding: 3,
+        dst_array_element: 0,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 1,
+        p_image_info: &atlas_info,
+        ..Default::default()
+    };
+
+    device.update_descriptor_sets(&[descriptor_write, hud_descriptor_write, sampler_descriptor_write, atlas_descriptor_write], &[]);
     descriptor_sets
 }
 
@@ -228,10 +257,21 @@ fn main() {
 
         let mut dummy_text = ui::text::Text::new(&g_state.device, &g_state.device_memory_properties, 32);
 
-        let world = World::new(&g_state.device, &g_state.device_memory_properties);
+        registry::load_registry(Path::new(config::BLOCK_DEFINITIONS_PATH)).expect("unable to load block definitions");
+        atlas::load_atlas(Path::new(config::BLOCK_TEXTURES_PATH)).expect("unable to load block textures");
+        let block_atlas = atlas::atlas();
+        let atlas_texture = Texture::create_pixelated(&g_state, block_atlas.pixels(), block_atlas.width(), block_atlas.height());
+
+        let save_path = Path::new(config::SAVE_PATH);
+        let mut world = if save_path.exists() {
+            World::load(save_path).expect("unable to load saved world")
+        } else {
+            World::new()
+        };
+        let mut renderer = WorldRenderer::new();
+        renderer.generate_graphics_objects(&world, &g_state.device, &g_state.device_memory_properties);
 
-        let mut blocks = vec![BlockType::Grass; 8];
-        blocks[0] = BlockType::NoBlock;
+        let mut streamer = Streamer::new(config::RENDER_DISTANCE);
 
         // ++++++++++++++
         let matrix_buffer = Buffer::new(
@@ -255,16 +295,19 @@ fn main() {
         let descriptor_sets = create_descriptor_sets(
             &g_state.device, descriptor_pool,
             descriptor_set_layout, matrix_buffer.vk_buffer, 
-            hud_matrix_buffer.vk_buffer, &deja_vu.texture);
+            hud_matrix_buffer.vk_buffer, &deja_vu.texture, &atlas_texture);
 
         let pipeline_layout = pipeline_layout(&g_state, &descriptor_set_layout);
 
         let world_fragment_shader_module = shader_module(&g_state, ShaderType::World, ShaderStage::Fragment);
         let world_vertex_shader_module = shader_module(&g_state, ShaderType::World, ShaderStage::Vertex);
+        let block_fragment_shader_module = shader_module(&g_state, ShaderType::Block, ShaderStage::Fragment);
+        let block_vertex_shader_module = shader_module(&g_state, ShaderType::Block, ShaderStage::Vertex);
         let hud_fragment_shader_module = shader_module(&g_state, ShaderType::Hud, ShaderStage::Fragment);
         let hud_vertex_shader_module = shader_module(&g_state, ShaderType::Hud, ShaderStage::Vertex);
 
         let world_shader_stages = shader_stage_create_infos(world_vertex_shader_module, world_fragment_shader_module);
+        let block_shader_stages = shader_stage_create_infos(block_vertex_shader_module, block_fragment_shader_module);
         let hud_shader_stages = shader_stage_create_infos(hud_vertex_shader_module, hud_fragment_shader_module);
 
         let viewports = viewports(&g_state);
@@ -278,6 +321,10 @@ fn main() {
         let textured_bindings = TexturedVertex::binding_description();
         let textured_input_state = vertex_input_state(&textured_bindings, &textured_attrs);
 
+        let block_attrs = BlockVertex::attribute_desctiptions();
+        let block_bindings = BlockVertex::binding_description();
+        let block_input_state = vertex_input_state(&block_bindings, &block_attrs);
+
         let world_pipeline = Pipeline::new(PipelineType::World, &scissors, &viewports);
         let graphic_pipeline_info = world_pipeline.create_info(&world_shader_stages, &colored_input_state, render_pass, pipeline_layout);
         
@@ -287,10 +334,19 @@ fn main() {
         let hud_pipeline = Pipeline::new(PipelineType::Hud, &scissors, &viewports);
         let hud_pipeline_info = hud_pipeline.create_info(&hud_shader_stages, &textured_input_state, render_pass, pipeline_layout);
 
+        // textured segments (see `meshing::TexturedMesher`) are drawn with the block pipelines
+        let block_pipeline_info = world_pipeline.create_info(&block_shader_stages, &block_input_state, render_pass, pipeline_layout);
+        let block_line_pipeline_info = world_line_pipeline.create_info(&block_shader_stages, &block_input_state, render_pass, pipeline_layout);
+
+        let translucent_pipeline = Pipeline::new(PipelineType::Translucent, &scissors, &viewports);
+        let translucent_pipeline_info = translucent_pipeline.create_info(&world_shader_stages, &colored_input_state, render_pass, pipeline_layout);
+        let translucent_block_pipeline_info = translucent_pipeline.create_info(&block_shader_stages, &block_input_state, render_pass, pipeline_layout);
+
         let graphics_pipelines = g_state.device
             .create_graphics_pipelines(
                 vk::PipelineCache::null(),
-                &[graphic_pipeline_info, hud_pipeline_info, line_pipeline_info],
+                &[graphic_pipeline_info, hud_pipeline_info, line_pipeline_info, block_pipeline_info, block_line_pipeline_info,
+                  translucent_pipeline_info, translucent_block_pipeline_info],
                 None,
             )
             .expect("Unable to create graphics pipeline");
@@ -304,8 +360,12 @@ fn main() {
         println!("target seconds per tick: {:?}", time::Duration::from_secs_f64(SECONDS_PER_TICK));
 
         let channel = ui::io::command_line::stdin_channel();
+        let mut clipboard = None;
 
         let mut pipeline_index: usize = 0;
+        let mut block_pipeline_index: usize = 3;
+        // [coloured, textured]
+        let mut translucent_pipeline_indices: [usize; 2] = [5, 6];
 
         while !g_state.window.should_close() {
 
@@ -326,6 +386,10 @@ fn main() {
 
             dummy_text.update(&format!("{:.2} {:.2} {:.2}", cam.ray.origin.x, cam.ray.origin.y, cam.ray.origin.z), &deja_vu, &Vec2::new(860., 440.), (XDir::XPos, YDir::YPos));
 
+            // the previous frame has to be finished before graphics objects can be replaced
+            g_state.device.wait_for_fences(&[g_state.draw_commands_reuse_fence], true, u64::MAX).unwrap();
+            streamer.remesh_dirty(&mut world, &mut renderer, &g_state.device, &g_state.device_memory_properties);
+
             let (present_index, _) = g_state
                 .swapchain_loader
                 .acquire_next_image(
@@ -379,10 +443,27 @@ fn main() {
                     // device.cmd_bind_index_buffer(draw_command_buffer, triangle.index_buffer().vk_buffer, 0, vk::IndexType::UINT32);
                     // device.cmd_draw_indexed(draw_command_buffer, triangle.indices().len() as u32, 1, 0, 0, 1);
 
-                    for object in &world.objects {
+                    let draw = |object: &RawObject| {
                         device.cmd_bind_vertex_buffers(draw_command_buffer, 0, &[object.vertex_buffer.vk_buffer], &[0]);
                         device.cmd_bind_index_buffer(draw_command_buffer, object.index_buffer.vk_buffer, 0, vk::IndexType::UINT32);
                         device.cmd_draw_indexed(draw_command_buffer, object.index_count, 1, 0, 0, 1);
+                    };
+
+                    let opaque_objects = || renderer.opaque_objects();
+                    opaque_objects().filter(|o| !o.textured).for_each(draw);
+
+                    device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, graphics_pipelines[block_pipeline_index]);
+                    opaque_objects().filter(|o| o.textured).for_each(draw);
+
+                    // translucent faces are blended over everything behind them, so they are drawn last and from back to front
+                    let mut bound = None;
+                    for object in renderer.translucent_objects_back_to_front(cam.ray.origin) {
+                        let pipeline = translucent_pipeline_indices[object.textured as usize];
+                        if bound != Some(pipeline) {
+                            device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, graphics_pipelines[pipeline]);
+                            bound = Some(pipeline);
+                        }
+                        draw(object);
                     }
 
                     device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, graphics_pipelines[1]);
@@ -413,6 +494,10 @@ fn main() {
                     input_state.update_from_event(&event);
                 }
                 cam.update_from_input_state(&input_state);
+
+                // the previous frame has to be finished before graphics objects can be freed
+                g_state.device.wait_for_fences(&[g_state.draw_commands_reuse_fence], true, u64::MAX).unwrap();
+                streamer.update(&mut world, &mut renderer, &cam, &g_state.device, &g_state.device_memory_properties);
     
                 if input_state.escape {
                     g_state.window.set_should_close(true);
@@ -421,8 +506,12 @@ fn main() {
 
                 if input_state.m {
                     pipeline_index = 2;
+                    block_pipeline_index = 4;
+                    translucent_pipeline_indices = [2, 4];
                 } else {
                     pipeline_index = 0;
+                    block_pipeline_index = 3;
+                    translucent_pipeline_indices = [5, 6];
                 }
 
                 // for (object, vertex_buffer, _) in &mut object_buffers {
@@ -434,9 +523,14 @@ fn main() {
                 //     }
                 // }
 
-                let cmds = ui::io::command_line::commands(&channel);
-                if cmds.len() > 0 {
-                    println!("{:?}", cmds);
+                for cmd in ui::io::command_line::commands(&channel) {
+                    if cmd.trim().is_empty() {
+                        continue;
+                    }
+                    match cmd.parse::<ui::io::command_line::Command>() {
+                        Ok(command) => ui::io::command_line::cl_println(&command.execute(&mut world, &mut clipboard)),
+                        Err(e) => ui::io::command_line::cl_println(&e),
+                    }
                 }
             }
 
@@ -446,6 +540,10 @@ fn main() {
         }
 
         g_state.device.device_wait_idle().unwrap();
+        if let Err(e) = world.save(save_path) {
+            println!("unable to save world to {:?}: {}", save_path, e);
+        }
+
         for pipeline in graphics_pipelines {
             g_state.device.destroy_pipeline(pipeline, None);
         }
@@ -456,6 +554,8 @@ fn main() {
 
         g_state.device.destroy_shader_module(world_vertex_shader_module, None);
         g_state.device.destroy_shader_module(world_fragment_shader_module, None);
+        g_state.device.destroy_shader_module(block_vertex_shader_module, None);
+        g_state.device.destroy_shader_module(block_fragment_shader_module, None);
         g_state.device.destroy_shader_module(hud_vertex_shader_module, None);
         g_state.device.destroy_shader_module(hud_fragment_shader_module, None);
 
@@ -463,13 +563,11 @@ fn main() {
         // triangle.vertex_buffer().free(&g_state.device);
 
         deja_vu.texture.free(&g_state);
+        atlas_texture.free(&g_state);
         dummy_text.index_buffer().free(&g_state.device);
         dummy_text.vertex_buffer().free(&g_state.device);
 
-        for object in &world.objects {
-            object.vertex_buffer.free(&g_state.device);
-            object.index_buffer.free(&g_state.device);
-        }
+        renderer.free(&g_state.device);
 
         matrix_buffer.free(&g_state.device);
         hud_matrix_buffer.free(&g_state.device);
@@ -0,0 +1,15 @@
+#version 400
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+layout (location = 0) in vec2 o_tex_coord;
+layout (location = 1) in float o_shade;
+
+layout (binding = 3) uniform sampler2D atlas;
+
+layout (location = 0) out vec4 uFragColor;
+
+void main() {
+    vec4 color = texture(atlas, o_tex_coord);
+    uFragColor = vec4(color.rgb * o_shade, color.a);
+}
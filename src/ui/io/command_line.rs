@@ -5,14 +5,29 @@ use std::sync::mpsc::{
     Receiver,
     TryRecvError,
 };
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::thread;
+use crate::graphics::{export::{ExportFormat, ExportMesh}, meshing};
+use crate::world::{
+    Axis,
+    World,
+    block::BlockType,
+    error::WorldError,
+    icoords::ICoords,
+    region::{Clipboard, Region},
+    schematic::Schematic,
+    vox::{VoxFile, VOX_EXTENSION},
+    state::BlockState,
+};
 
 pub fn cl_start() {
 }
 
 pub fn cl_println(s: &str) {
     let mut lock = io::stdout().lock();
-    lock.write(b"line").unwrap();
+    writeln!(lock, "{}", s).unwrap();
 }
 
 // fetch all the lines from the stdin channel and return them
@@ -20,7 +35,7 @@ pub fn commands(receiver: &Receiver<String>) -> Vec<String> {
     let mut commands = Vec::<String>::new();
     loop {
         match receiver.try_recv() {
-            Ok(key) => commands.push(String::from(key)),
+            Ok(key) => commands.push(key),
             Err(TryRecvError::Empty) => break,
             Err(TryRecvError::Disconnected) => panic!("stdin channel disconnected"),
         }
@@ -41,3 +56,250 @@ pub fn stdin_channel() -> Receiver<String> {
     rx
 }
 
+
+/// commands that can be entered on stdin
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Undo,
+    Redo,
+    History,
+    Fill {
+        region: Region,
+        block: BlockState,
+    },
+    Replace {
+        region: Region,
+        from: BlockType,
+        to: BlockState,
+    },
+    Copy {
+        region: Region,
+    },
+    Paste {
+        origin: ICoords,
+        include_air: bool,
+    },
+    /// rotates the clipboard by quarter turns around the y axis
+    Rotate {
+        turns: u32,
+    },
+    Mirror {
+        axis: Axis,
+    },
+    /// saves the clipboard as a schematic or, if the path ends in `.vox`, as a MagicaVoxel file
+    Export {
+        path: PathBuf,
+    },
+    /// loads a schematic or MagicaVoxel file into the clipboard
+    Import {
+        path: PathBuf,
+    },
+    /// writes the meshes of the l1 segments intersecting `region` to `path` as obj, ply or gltf,
+    /// meshed with the mesher called `mesher` or the current one
+    ExportMesh {
+        region: Region,
+        path: PathBuf,
+        mesher: Option<String>,
+    },
+    /// switches to the mesher called `name`, or shows the current one
+    Mesher {
+        name: Option<String>,
+    },
+    Help,
+}
+
+const HELP: &str = "commands:
+  undo | redo | history
+  fill <x1> <y1> <z1> <x2> <y2> <z2> <block>
+  replace <x1> <y1> <z1> <x2> <y2> <z2> <from> <to>
+  copy <x1> <y1> <z1> <x2> <y2> <z2>
+  paste <x> <y> <z> [skip-air]
+  rotate <quarter turns>
+  mirror <x|y|z>
+  export <path> | import <path>   (schematic, or MagicaVoxel if the path ends in .vox)
+  export-mesh <x1> <y1> <z1> <x2> <y2> <z2> <path> [mesher]   (obj, ply, gltf or glb, whole segments)
+  mesher [name]
+blocks are written as `name` or `name[property=value,...]`, e.g. `log[axis=x]`";
+
+fn is_vox(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new(VOX_EXTENSION))
+}
+
+fn mesher_names() -> String {
+    meshing::meshers().iter().map(|m| m.name()).collect::<Vec<_>>().join(", ")
+}
+
+fn parse_coords(words: &[&str]) -> Result<ICoords, String> {
+    let parse = |w: &str| w.parse::<i64>().map_err(|_| format!("invalid coordinate `{}`", w));
+    Ok(ICoords::new(parse(words[0])?, parse(words[1])?, parse(words[2])?))
+}
+
+fn parse_region(words: &[&str]) -> Result<Region, String> {
+    Ok(Region::new(parse_coords(&words[0..3])?, parse_coords(&words[3..6])?))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words[..] {
+            ["undo"] => Ok(Command::Undo),
+            ["redo"] => Ok(Command::Redo),
+            ["history"] => Ok(Command::History),
+            ["help"] => Ok(Command::Help),
+            ["fill", .., block] if words.len() == 8 => Ok(Command::Fill {
+                region: parse_region(&words[1..7])?,
+                block: block.parse()?,
+            }),
+            ["replace", .., from, to] if words.len() == 9 => Ok(Command::Replace {
+                region: parse_region(&words[1..7])?,
+                from: BlockType::from_name(from).ok_or_else(|| format!("unknown block type `{}`", from))?,
+                to: to.parse()?,
+            }),
+            ["copy", ..] if words.len() == 7 => Ok(Command::Copy { region: parse_region(&words[1..7])? }),
+            ["paste", _, _, _] => Ok(Command::Paste { origin: parse_coords(&words[1..4])?, include_air: true }),
+            ["paste", _, _, _, "skip-air"] => Ok(Command::Paste { origin: parse_coords(&words[1..4])?, include_air: false }),
+            ["rotate", turns] => Ok(Command::Rotate {
+                turns: turns.parse::<i64>().map_err(|_| format!("invalid number of turns `{}`", turns))?.rem_euclid(4) as u32,
+            }),
+            ["export", path] => Ok(Command::Export { path: PathBuf::from(path) }),
+            ["import", path] => Ok(Command::Import { path: PathBuf::from(path) }),
+            ["export-mesh", ..] if words.len() == 8 || words.len() == 9 => {
+                let path = PathBuf::from(words[7]);
+                if ExportFormat::from_path(&path).is_none() {
+                    return Err(format!("unknown mesh format of `{}`, available: {}", words[7], ExportFormat::EXTENSIONS.join(", ")));
+                }
+                let mesher = words.get(8).map(|&name| match meshing::mesher(name) {
+                    Some(_) => Ok(String::from(name)),
+                    None => Err(format!("unknown mesher `{}`, available: {}", name, mesher_names())),
+                }).transpose()?;
+                Ok(Command::ExportMesh { region: parse_region(&words[1..7])?, path, mesher })
+            },
+            ["mesher"] => Ok(Command::Mesher { name: None }),
+            ["mesher", name] => match meshing::mesher(name) {
+                Some(_) => Ok(Command::Mesher { name: Some(String::from(name)) }),
+                None => Err(format!("unknown mesher `{}`, available: {}", name, mesher_names())),
+            },
+            ["mirror", axis] => Ok(Command::Mirror {
+                axis: match axis {
+                    "x" => Axis::X,
+                    "y" => Axis::Y,
+                    "z" => Axis::Z,
+                    _ => return Err(format!("invalid axis `{}`", axis)),
+                },
+            }),
+            _ => Err(format!("unknown command `{}`, type `help` for a list of commands", s.trim())),
+        }
+    }
+}
+
+impl Command {
+    /// runs the command and returns the message to show to the user
+    /// - `clipboard`: the blocks copied by `copy`, shared between commands
+    pub fn execute(&self, world: &mut World, clipboard: &mut Option<Clipboard>) -> String {
+        let changed = |result: Result<usize, WorldError>| match result {
+            Ok(n) => format!("changed {} blocks", n),
+            Err(e) => e.to_string(),
+        };
+        match self {
+            Command::Undo => match world.undo() {
+                Some(name) => format!("undid {}", name),
+                None => String::from("nothing to undo"),
+            },
+            Command::Redo => match world.redo() {
+                Some(name) => format!("redid {}", name),
+                None => String::from("nothing to redo"),
+            },
+            Command::History => {
+                let history = world.history();
+                format!("{} operations to undo, {} to redo, {} KiB used", history.n_undo(), history.n_redo(), history.memory_usage() / 1024)
+            },
+            Command::Fill { region, block } => changed(world.fill(*region, *block)),
+            Command::Replace { region, from, to } => changed(world.replace(*region, *from, *to)),
+            Command::Copy { region } => match world.copy(*region) {
+                Ok(copied) => {
+                    *clipboard = Some(copied);
+                    let size = region.size();
+                    format!("copied {}x{}x{} blocks", size.x, size.y, size.z)
+                },
+                Err(e) => e.to_string(),
+            },
+            Command::Paste { origin, include_air } => match clipboard {
+                Some(clipboard) => changed(world.paste(clipboard, *origin, *include_air)),
+                None => String::from("the clipboard is empty"),
+            },
+            Command::Rotate { turns } => match clipboard {
+                Some(c) => {
+                    *c = c.rotated_y(*turns);
+                    String::from("rotated the clipboard")
+                },
+                None => String::from("the clipboard is empty"),
+            },
+            Command::Mirror { axis } => match clipboard {
+                Some(c) => {
+                    *c = c.mirrored(*axis);
+                    String::from("mirrored the clipboard")
+                },
+                None => String::from("the clipboard is empty"),
+            },
+            Command::Export { path } => match clipboard {
+                Some(c) => {
+                    let result = if is_vox(path) {
+                        VoxFile::from_clipboard(c).save(path)
+                    } else {
+                        let mut schematic = Schematic::new(c.clone());
+                        if let Some(name) = path.file_stem() {
+                            schematic.metadata.insert(String::from("name"), name.to_string_lossy().into_owned());
+                        }
+                        schematic.save(path)
+                    };
+                    match result {
+                        Ok(()) => format!("exported the clipboard to {:?}", path),
+                        Err(e) => format!("unable to export to {:?}: {}", path, e),
+                    }
+                },
+                None => String::from("the clipboard is empty"),
+            },
+            Command::Import { path } => {
+                let blocks = if is_vox(path) {
+                    VoxFile::load(path).and_then(|vox| match vox.to_clipboard() {
+                        Ok(Some(blocks)) => Ok(blocks),
+                        Ok(None) => Err(io::Error::new(io::ErrorKind::InvalidData, "the file contains no voxels")),
+                        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                    })
+                } else {
+                    Schematic::load(path).map(|schematic| schematic.blocks)
+                };
+                match blocks {
+                    Ok(blocks) => {
+                        let size = blocks.size();
+                        *clipboard = Some(blocks);
+                        format!("imported {}x{}x{} blocks from {:?}", size.x, size.y, size.z, path)
+                    },
+                    Err(e) => format!("unable to import {:?}: {}", path, e),
+                }
+            },
+            Command::ExportMesh { region, path, mesher } => {
+                let mesher = match mesher.as_deref().and_then(meshing::mesher) {
+                    Some(mesher) => meshing::seeded(mesher, world.seed()),
+                    None => world.mesher(),
+                };
+                let mesh = ExportMesh::from_world(world, world.loaded_l1_segments_in(*region), mesher.as_ref());
+                match mesh.save(path) {
+                    Ok(()) => format!("exported {} triangles to {:?}", mesh.n_triangles(), path),
+                    Err(e) => format!("unable to export to {:?}: {}", path, e),
+                }
+            },
+            Command::Mesher { name: None } => format!("meshing with {}, available: {}", world.mesher().name(), mesher_names()),
+            Command::Mesher { name: Some(name) } => match meshing::mesher(name) {
+                Some(mesher) => {
+                    world.set_mesher(mesher);
+                    format!("meshing with {}", name)
+                },
+                None => format!("unknown mesher `{}`", name),
+            },
+            Command::Help => String::from(HELP),
+        }
+    }
+}
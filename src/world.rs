@@ -1,21 +1,35 @@
 pub mod segment;
 pub mod noise;
 pub mod icoords;
-pub mod object;
 pub mod ray;
 pub mod size;
 pub mod block;
-
-use std::collections::HashMap;
-use noise::*;
-use glam::Vec3;
-use crate::graphics::meshing;
+pub mod persistence;
+pub mod palette;
+pub mod error;
+pub mod generation;
+pub mod registry;
+pub mod state;
+pub mod history;
+pub mod region;
+pub mod schematic;
+pub mod vox;
+pub mod light;
+pub mod shape;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use crate::graphics::meshing::{self, Mesher, SegmentMesh};
+use crate::config;
 use crate::profiler::*;
-use object::*;
-use size::*;
 use segment::*;
 use block::*;
+use state::*;
 use icoords::*;
+use error::*;
+use history::*;
 
 // the indices of the triangles constituting the block face facing in negative x direction
 const INDICES_NEG_X: [u32; 6] = [
@@ -60,13 +74,14 @@ pub const BL_VERTICES: [[u64; 3]; 8] = [
     [1, 1, 1]
 ];
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Axis {
     X,
     Y,
     Z,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Face {
     XPos,
     XNeg,
@@ -104,6 +119,17 @@ impl Face {
         }
     }
 
+    pub fn opposite(&self) -> Face {
+        match self {
+            Face::XPos => Face::XNeg,
+            Face::XNeg => Face::XPos,
+            Face::YPos => Face::YNeg,
+            Face::YNeg => Face::YPos,
+            Face::ZPos => Face::ZNeg,
+            Face::ZNeg => Face::ZPos,
+        }
+    }
+
     pub fn indices(&self) -> [u32; 6] {
         match self {
             Face::XPos => {
@@ -128,27 +154,104 @@ impl Face {
     }
 }
 
-pub struct World<'a> {
-    pub objects: Vec<RawObject<'a>>,
+/// numbers of loaded segments and blocks, see `World::stats`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorldStats {
+    pub l3_segments: usize,
+    pub l2_segments: usize,
+    pub l1_segments: usize,
+    pub solid_blocks: usize,
+    /// approximate bytes used by the l1 segments
+    pub memory_usage: usize,
+}
+
+/// the terrain of a world and everything needed to generate, edit, light and mesh it.
+/// the graphics objects of its segments are kept by a `graphics::world_renderer::WorldRenderer`.
+pub struct World {
     pub terrain: HashMap<ICoords, L3Segment>,
     seed: u32,
+    // global coordinates of the l1 segments whose mesh is out of date because blocks changed
+    dirty: HashSet<ICoords>,
+    history: History,
+    // blocks changed in a way that affects light, since the light was last updated
+    light_updates: Vec<ICoords>,
+    // global coordinates of the l1 segments with so many changed blocks that they are relit as a whole
+    light_segments: HashSet<ICoords>,
+    // global coordinates of the l1 segments touching a block whose light changed
+    light_changed: HashSet<ICoords>,
+    mesher: Arc<dyn Mesher>,
+    // whether the mesher has changed since all meshes were last rebuilt
+    mesher_changed: bool,
 }
 
-impl<'a> World<'a> {
-    pub unsafe fn new(device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) -> Self {
-        let mut w = World {
-            objects: Vec::new(),
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    /// an empty world with the seed `config::WORLD_SEED`,
+    /// segments are generated on demand (see `generate_l1_segment` and `graphics::streaming`)
+    pub fn new() -> Self {
+        Self::with_seed(config::WORLD_SEED)
+    }
+
+    /// an empty world whose terrain is generated with `seed`
+    pub fn with_seed(seed: u32) -> Self {
+        World {
             terrain: HashMap::new(),
-            seed: 12,
+            seed,
+            dirty: HashSet::new(),
+            history: History::new(config::HISTORY_MEMORY_LIMIT),
+            light_updates: Vec::new(),
+            light_segments: HashSet::new(),
+            light_changed: HashSet::new(),
+            mesher: meshing::seeded(default_mesher(), seed),
+            mesher_changed: false,
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// saves the terrain of the world into the directory at `path`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        p_start("world.save");
+        let result = persistence::save_world(path, &persistence::WorldHeader { seed: self.seed }, &self.terrain);
+        p_end("world.save");
+        result
+    }
+
+    /// loads a world that was previously saved to `path`.
+    /// call `WorldRenderer::generate_graphics_objects` to render the loaded terrain.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        p_start("world.load");
+        let (header, terrain) = persistence::load_world(path)?;
+        p_end("world.load");
+        let mut world = World {
+            terrain,
+            seed: header.seed,
+            dirty: HashSet::new(),
+            history: History::new(config::HISTORY_MEMORY_LIMIT),
+            light_updates: Vec::new(),
+            light_segments: HashSet::new(),
+            light_changed: HashSet::new(),
+            mesher: meshing::seeded(default_mesher(), header.seed),
+            mesher_changed: false,
         };
 
-        for l1c in (Size3D {x: 4, y: 4, z: 4}) {
-        // for (l1x, l1y, l1z) in [(0, 0, 0)] {
-            w.generate_l1_segment(l1c * L1_SIZE_BL.into());
+        // light is not saved. lighting from the top down lets sky light pass through each column only once.
+        p_start("world.load.light");
+        let mut segments = world.l1_segment_coords();
+        segments.sort_by_key(|c| -c.y);
+        for l1_glob in segments {
+            world.light_l1_segment(l1_glob);
         }
-
-        w.generate_graphics_objects(device, device_memory_properties);
-        w
+        world.light_changed.clear();
+        p_end("world.load.light");
+        Ok(world)
     }
 
     fn l3_segment(&self, coords: ICoords) -> Option<&L3Segment> {
@@ -181,7 +284,7 @@ impl<'a> World<'a> {
         l3_seg.sub_segments[L3_SIZE.c1d(l2c) as usize].as_mut().unwrap()
     }
 
-    fn l1_segment(&self, coords: ICoords) -> Option<&L1Segment> {
+    pub fn l1_segment(&self, coords: ICoords) -> Option<&L1Segment> {
         if let Some(l2_seg) = self.l2_segment(coords) {
             let l1_coords = coords.l1_loc();
             return l2_seg.sub_segments[L2_SIZE.c1d(l1_coords) as usize].as_ref();
@@ -190,6 +293,54 @@ impl<'a> World<'a> {
         None
     }
 
+    fn l1_segment_mut(&mut self, coords: ICoords) -> Option<&mut L1Segment> {
+        let l3_seg = self.terrain.get_mut(&coords.l3_glob())?;
+        let l2_seg = l3_seg.sub_segments[L3_SIZE.c1d(coords.l2_loc()) as usize].as_mut()?;
+        l2_seg.sub_segments[L2_SIZE.c1d(coords.l1_loc()) as usize].as_mut()
+    }
+
+    /// counts the loaded segments and their solid blocks
+    pub fn stats(&self) -> WorldStats {
+        let mut stats = WorldStats { l3_segments: self.terrain.len(), ..WorldStats::default() };
+        for l3 in self.terrain.values() {
+            stats.l2_segments += l3.number_of_l2_segments();
+            for l2 in l3.sub_segments.iter().flatten() {
+                stats.l1_segments += l2.number_of_l1_segments();
+                for l1 in l2.sub_segments.iter().flatten() {
+                    stats.solid_blocks += l1.number_of_solid_blocks();
+                    stats.memory_usage += l1.memory_usage();
+                }
+            }
+        }
+        stats
+    }
+
+    /// global coordinates of all loaded l1 segments
+    pub fn l1_segment_coords(&self) -> Vec<ICoords> {
+        let mut segments = Vec::new();
+        for (&l3c, l3) in &self.terrain {
+            for l2c in L3_SIZE {
+                if let Some(l2) = &l3.sub_segments[L3_SIZE.c1d(l2c) as usize] {
+                    for l1c in L2_SIZE {
+                        if l2.sub_segments[L2_SIZE.c1d(l1c) as usize].is_some() {
+                            segments.push((l3c * L3_SIZE.into() + l2c) * L2_SIZE.into() + l1c);
+                        }
+                    }
+                }
+            }
+        }
+        segments
+    }
+
+    /// like `create_or_get_l1`, but a new segment is lit before it is returned
+    fn create_or_get_lit_l1(&mut self, coords: ICoords) -> &mut L1Segment {
+        if self.l1_segment(coords).is_none() {
+            self.create_or_get_l1(coords);
+            self.light_l1_segment(coords.l1_glob());
+        }
+        self.create_or_get_l1(coords)
+    }
+
     fn create_or_get_l1(&mut self, coords: ICoords) -> &mut L1Segment {
         let l2_seg = self.create_or_get_l2(coords);
         let l1_coords = coords.l1_loc();
@@ -201,87 +352,274 @@ impl<'a> World<'a> {
     }
 
     /// * `coords` - coordinates of the 0 0 0 block in the desired l1_segment
-    fn generate_l1_segment(&mut self, coords: ICoords) {
-        p_start("generate_l1_segment");
-        // let noise = noise::Perlin::new(self.seed);
-        let l1_seg = self.create_or_get_l1(coords);
-
-        for delta in L1_SIZE_BL {
-            p_start("noise.get");
-            let v = perlin(Vec3::new(
-                (coords.x + delta.x) as f32 / 256., 
-                (coords.y + delta.y) as f32 / 32., 
-                (coords.z + delta.z) as f32 / 256.));
-            // let v = noise.get([
-            //     (coords.x + delta.x) as f64 / 50.,
-            //     (coords.y + delta.y) as f64 / 50.,
-            //     (coords.z + delta.z) as f64 / 50.]);
-            // println!("perlin({:?})", v);
-            p_end("noise.get");
-            if v > 0. {
-                l1_seg.blocks[L1_SIZE_BL.c1d(delta) as usize] = BlockType::Grass;
-            }
+    pub fn generate_l1_segment(&mut self, coords: ICoords) {
+        if let Some(changed) = self.insert_l1_segment(coords, generation::generate_l1_segment(self.seed, coords)) {
+            self.dirty.extend(changed);
         }
-        p_end("generate_l1_segment");
     }
 
-    unsafe fn generate_graphics_objects(&mut self, device: &'a ash::Device, device_memory_properties: &ash::vk::PhysicalDeviceMemoryProperties) {
-        for l3 in self.terrain.values() {
-            for l2c in L3_SIZE {
-                if let Some(l2) = &l3.sub_segments[L3_SIZE.c1d(l2c) as usize] {
-                    for l1c in L2_SIZE {
-                        if let Some(l1) = &l2.sub_segments[L2_SIZE.c1d(l1c) as usize] {
-                            let offset = l2c * L2_SIZE_BL.into() + l1c * L1_SIZE_BL.into();
-
-                            p_start("mesh_l1_segment");
-                            let (vertices, indices) = meshing::mesh_l1_segment(l1, 
-                                [
-                                    self.l1_segment((l1c + ICoords::new(1, 0, 0)) * L1_SIZE.into()),
-                                    self.l1_segment((l1c + ICoords::new(-1, 0, 0)) * L1_SIZE.into()),
-                                    self.l1_segment((l1c + ICoords::new(0, 1, 0)) * L1_SIZE.into()),
-                                    self.l1_segment((l1c + ICoords::new(0, -1, 0)) * L1_SIZE.into()),
-                                    self.l1_segment((l1c + ICoords::new(0, 0, 1)) * L1_SIZE.into()),
-                                    self.l1_segment((l1c + ICoords::new(0, 0, -1)) * L1_SIZE.into())], 
-                                Vec3::new(offset.x as f32, offset.y as f32, offset.z as f32));
-                            p_end("mesh_l1_segment");
-
-                            if vertices.len() == 0 || indices.len() == 0 {
-                                continue;
-                            }
-                            let o = RawObject::new(device, device_memory_properties, &vertices, &indices);
-                            self.objects.push(o);
-                        }
-                    }
-                }
-            }
+    /// inserts `segment` as the l1 segment containing `coords` and lights it, unless that segment already exists,
+    /// e.g. because a block has been set there while `segment` was being generated.
+    /// returns `None` if `segment` was not inserted, otherwise the global coordinates of the other l1 segments
+    /// whose light changed, their meshes are out of date.
+    pub fn insert_l1_segment(&mut self, coords: ICoords, segment: L1Segment) -> Option<HashSet<ICoords>> {
+        let l2_seg = self.create_or_get_l2(coords);
+        let slot = &mut l2_seg.sub_segments[L2_SIZE.c1d(coords.l1_loc()) as usize];
+        if slot.is_some() {
+            return None;
         }
+        *slot = Some(segment);
+        self.light_l1_segment(coords.l1_glob());
+        let mut changed = std::mem::take(&mut self.light_changed);
+        changed.remove(&coords.l1_glob());
+        Some(changed)
     }
 
-    pub fn get_block(self, coords: ICoords) -> BlockType {
-        let l2c = L2_SIZE.c1d(coords.l2_loc()) as usize;
-        let l1c = L1_SIZE.c1d(coords.l1_loc()) as usize;
-        let blc = L3_SIZE.c1d(coords.bl_loc()) as usize;
+    /// meshes the l1 segment at global segment coordinates `l1_glob`, taking its neighbours into account
+    pub fn mesh_l1_segment(&self, l1_glob: ICoords) -> Option<SegmentMesh> {
+        self.mesh_l1_segment_with(l1_glob, self.mesher.as_ref())
+    }
 
-        if let Some(l2) = &self.terrain[&coords.l3_glob()].sub_segments[l2c] {
-            if let Some(l1) = &l2.sub_segments[l1c] {
-                return l1.blocks[blc];
-            }
+    /// like `mesh_l1_segment`, but with `mesher` instead of the mesher of the world
+    pub fn mesh_l1_segment_with(&self, l1_glob: ICoords, mesher: &dyn Mesher) -> Option<SegmentMesh> {
+        // global coordinates of the 0 0 0 block of the segment
+        let offset = l1_glob * L1_SIZE_BL.into();
+        let l1 = self.l1_segment(offset)?;
+
+        p_start("mesh_l1_segment");
+        let mesh = mesher.mesh(l1, 
+            Face::all().map(|face| self.l1_segment(offset + face.numeric() * L1_SIZE_BL.into())),
+            offset.vec3());
+        p_end("mesh_l1_segment");
+        Some(mesh)
+    }
+
+    pub fn mesher(&self) -> Arc<dyn Mesher> {
+        self.mesher.clone()
+    }
+
+    /// meshes all segments with `mesher` from now on.
+    /// the segments are not marked dirty, rebuilding every mesh is left to the streamer (see `take_mesher_changed`).
+    pub fn set_mesher(&mut self, mesher: Arc<dyn Mesher>) {
+        self.mesher = meshing::seeded(mesher, self.seed);
+        self.mesher_changed = true;
+    }
+
+    /// returns whether the mesher has been changed since the last call
+    pub fn take_mesher_changed(&mut self) -> bool {
+        std::mem::take(&mut self.mesher_changed)
+    }
+
+    /// returns the block at `coords`, or `BlockState::NO_BLOCK` if it is not loaded or out of range
+    pub fn get_block(&self, coords: ICoords) -> BlockState {
+        self.try_get_block(coords).unwrap_or_default()
+    }
+
+    pub fn try_get_block(&self, coords: ICoords) -> Result<BlockState, WorldError> {
+        if !coords.in_range() {
+            return Err(WorldError::OutOfRange(coords));
+        }
+        match self.l1_segment(coords) {
+            Some(l1) => Ok(l1.get(coords.bl_loc())),
+            None => Err(WorldError::Unloaded(coords)),
         }
-        BlockType::NoBlock
     }
 
-    pub fn set_block(&mut self, coords: ICoords, block: BlockType) {
-        if coords.x >= L3_SIZE_BL.x as i64 || coords.y >= L3_SIZE_BL.y as i64 || coords.z >= L3_SIZE_BL.z as i64 {
-            println!("[set_block]: coordinates ({}, {}, {}) are out of bounds", coords.x, coords.y, coords.z);
-            return;
+    /// sets the block at `coords` and returns the previous block.
+    /// the segments containing `coords` are created if they do not exist yet.
+    /// properties that the block type does not declare are reset, see `BlockState::normalized`.
+    /// fails with `WorldError::HistoryFull` if the change cannot be undone, see `History::reserve`.
+    pub fn set_block(&mut self, coords: ICoords, block: impl Into<BlockState>) -> Result<BlockState, WorldError> {
+        if !coords.in_range() {
+            return Err(WorldError::OutOfRange(coords));
+        }
+        let block = block.into().normalized();
+        let seg = self.create_or_get_lit_l1(coords);
+        let index = L1_SIZE_BL.c1d(coords.bl_loc()) as usize;
+        let (previous, full_size) = (seg.get_index(index), seg.blocks_memory_usage());
+        if previous == block {
+            return Ok(previous);
         }
 
-        let l2c = L2_SIZE.c1d(coords.l2_loc()) as usize;
-        let l1c = L1_SIZE.c1d(coords.l1_loc()) as usize;
-        let blc = L3_SIZE.c1d(coords.bl_loc()) as usize;
+        let open = self.history.is_recording();
+        if !open {
+            self.history.begin("set block");
+        }
+        let recorded = self.history.record_block(coords.l1_glob(), index, previous, full_size);
+        if let Ok(true) = recorded {
+            let image = self.create_or_get_l1(coords).blocks_image();
+            self.history.record_image(coords.l1_glob(), image);
+        }
+        if !open {
+            self.history.end();
+        }
+        recorded?;
+        self.set_block_unrecorded(coords, block);
+        self.update_light();
+        Ok(previous)
+    }
 
-        let l2 = self.terrain.get_mut(&coords.l3_glob()).unwrap().sub_segments[l2c].get_or_insert_with(L2Segment::default);
-        let l1 = l2.sub_segments[l1c].get_or_insert_with(L1Segment::default);
-        l1.blocks[blc] = block;
+    /// the caller has to call `update_light` afterwards
+    fn set_block_unrecorded(&mut self, coords: ICoords, block: BlockState) -> BlockState {
+        let previous = self.create_or_get_lit_l1(coords).set(coords.bl_loc(), block);
+        if previous != block {
+            self.mark_dirty(coords);
+        }
+        if light::affects_light(previous, block) {
+            self.light_updates.push(coords);
+        }
+        previous
     }
-}
\ No newline at end of file
+
+    /// relights the blocks and segments changed since the last call and marks the segments whose light changed as dirty
+    fn update_light(&mut self) {
+        let segments = std::mem::take(&mut self.light_segments);
+        if !segments.is_empty() {
+            p_start("world.update_light.segments");
+            self.relight_l1_segments(segments.into_iter().collect());
+            p_end("world.update_light.segments");
+        }
+        let positions = std::mem::take(&mut self.light_updates);
+        if !positions.is_empty() {
+            p_start("world.update_light");
+            self.relight(&positions);
+            p_end("world.update_light");
+        }
+        let changed = std::mem::take(&mut self.light_changed);
+        self.dirty.extend(changed);
+    }
+
+    /// groups all following block changes into one operation called `name`, until `end_operation` is called.
+    /// changes outside of an operation are undone one block at a time.
+    pub fn begin_operation(&mut self, name: &str) {
+        self.history.begin(name);
+    }
+
+    pub fn end_operation(&mut self) {
+        self.history.end();
+    }
+
+    /// exchanges the blocks of all segments changed by `operation` with the ones it recorded and relights them
+    fn swap_operation(&mut self, operation: &mut Operation) {
+        let segments: Vec<ICoords> = operation.segments().collect();
+        for &l1_glob in &segments {
+            operation.swap(l1_glob, self.create_or_get_l1(l1_glob * L1_SIZE_BL.into()));
+            self.dirty.insert(l1_glob);
+            self.dirty.extend(Face::all().map(|face| l1_glob + face.numeric()));
+        }
+        self.light_segments.extend(segments);
+        self.update_light();
+    }
+
+    /// reverts the latest operation and returns its name, or `None` if there is nothing to undo
+    pub fn undo(&mut self) -> Option<String> {
+        let mut operation = self.history.take_undo()?;
+        self.swap_operation(&mut operation);
+        let name = operation.name.clone();
+        self.history.push_undone(operation);
+        Some(name)
+    }
+
+    /// reapplies the latest undone operation and returns its name, or `None` if there is nothing to redo
+    pub fn redo(&mut self) -> Option<String> {
+        let mut operation = self.history.take_redo()?;
+        self.swap_operation(&mut operation);
+        let name = operation.name.clone();
+        self.history.push_redone(operation);
+        Some(name)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// marks the l1 segment containing the block at `coords` as dirty,
+    /// as well as the neighbouring segments whose faces touch the block
+    fn mark_dirty(&mut self, coords: ICoords) {
+        self.dirty.extend(touching_l1_segments(coords));
+    }
+
+    /// returns the global coordinates of all l1 segments that have been marked dirty since the last call
+    pub fn take_dirty(&mut self) -> HashSet<ICoords> {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+fn default_mesher() -> Arc<dyn Mesher> {
+    meshing::mesher(config::MESHER).unwrap_or_else(|| panic!("unknown mesher `{}` in config::MESHER", config::MESHER))
+}
+
+/// global coordinates of the l1 segment containing the block at `coords`
+/// and of the neighbouring segments whose faces touch the block
+fn touching_l1_segments(coords: ICoords) -> impl Iterator<Item = ICoords> {
+    let (l1_glob, local) = (coords.l1_glob(), coords.bl_loc());
+    let neighbours = Face::all().into_iter()
+        .filter(move |face| !L1_SIZE_BL.contains(local + face.numeric()))
+        .map(move |face| l1_glob + face.numeric());
+    std::iter::once(l1_glob).chain(neighbours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_across_the_l3_boundary_at_zero() {
+        let grass = BlockType::from_name("grass").unwrap();
+        let mut world = World::new();
+        let edge = L3_SIZE_BL.x as i64;
+        let positions = [
+            ICoords::new(-1, -1, -1),
+            ICoords::new(0, 0, 0),
+            ICoords::new(-1, 0, 0),
+            ICoords::new(0, -1, 0),
+            ICoords::new(0, 0, -1),
+            ICoords::new(-edge, 0, 0),
+            ICoords::new(-edge - 1, 0, 0),
+        ];
+        for &p in &positions {
+            assert_eq!(world.set_block(p, grass), Ok(BlockState::NO_BLOCK), "{:?}", p);
+        }
+        for &p in &positions {
+            assert_eq!(world.get_block(p), BlockState::from(grass), "{:?}", p);
+        }
+        // the neighbours on the other side of the boundaries stay empty
+        assert_eq!(world.get_block(ICoords::new(1, 0, 0)), BlockState::NO_BLOCK);
+        assert_eq!(world.get_block(ICoords::new(-2, -1, -1)), BlockState::NO_BLOCK);
+        // -edge lies in the same l3 segment as -1, -edge - 1 in the one before
+        assert_eq!(world.terrain.len(), 6);
+        assert_eq!(world.try_get_block(ICoords::new(-1000, 0, 0)), Err(WorldError::Unloaded(ICoords::new(-1000, 0, 0))));
+    }
+
+    #[test]
+    fn generated_segments_do_not_replace_edits() {
+        let grass = BlockType::from_name("grass").unwrap();
+        let mut world = World::new();
+        let offset = ICoords::new(-32, 0, 64);
+        let edited = offset + ICoords::new(3, 4, 5);
+        // the block is set while the segment is still being generated elsewhere
+        let generated = generation::generate_l1_segment(world.seed(), offset);
+        world.set_block(edited, grass).unwrap();
+        assert!(world.insert_l1_segment(offset, generated).is_none());
+        assert_eq!(world.get_block(edited), BlockState::from(grass));
+        assert_eq!(world.get_block(offset), BlockState::NO_BLOCK);
+
+        let empty = ICoords::new(0, 0, 0);
+        assert!(world.insert_l1_segment(empty, L1Segment::default()).is_some());
+        assert!(world.l1_segment(empty).is_some());
+    }
+
+    #[test]
+    fn changing_the_mesher_does_not_mark_segments_dirty() {
+        let mut world = World::new();
+        world.set_block(ICoords::new(0, 0, 0), BlockType::from_name("stone").unwrap()).unwrap();
+        world.take_dirty();
+        assert!(!world.take_mesher_changed());
+
+        world.set_mesher(meshing::mesher("greedy").unwrap());
+        // block edits are remeshed synchronously, a new mesher is left to the worker pool
+        assert!(world.take_dirty().is_empty());
+        assert!(world.take_mesher_changed());
+        assert!(!world.take_mesher_changed());
+    }
+}
@@ -1,25 +1,76 @@
+use super::error::WorldError;
+use super::registry::{registry, BlockProperties};
 
-#[derive(Clone, PartialEq, Eq, Copy, Debug)]
-pub enum BlockType {
-    NoBlock,
-    Grass
-}
+/// numeric id of a block type, as it is stored in segments and on disk.
+/// the properties of each id are defined in the block registry.
+#[derive(Clone, PartialEq, Eq, Hash, Copy, Debug, Default)]
+pub struct BlockType(pub u16);
+
+impl BlockType {
+    /// air, the only block type that is not defined in the registry file
+    pub const NO_BLOCK: BlockType = BlockType(0);
 
-impl Default for BlockType {
-    fn default() -> Self {
-        return Self::NoBlock;
+    /// looks up the block type called `name` in the registry
+    pub fn from_name(name: &str) -> Option<Self> {
+        registry().by_name(name)
+    }
+
+    /// the registered properties of this block type. panics if the id is not registered.
+    pub fn properties(&self) -> &'static BlockProperties {
+        registry().get(*self).unwrap_or_else(|| panic!("block id {} is not registered", self.0))
+    }
+
+    pub fn name(&self) -> &'static str {
+        &self.properties().name
     }
-}
 
-impl BlockType {
     pub fn color(&self) -> Option<[f32; 4]> {
-        match &self {
-            BlockType::Grass => Some([0.0, 1.0, 0.0, 1.0]),
-            _ => Some([1.0, 1.0, 1.0, 1.0])
+        if *self == Self::NO_BLOCK {
+            return None;
         }
+        Some(self.properties().color)
     }
 
     pub fn is_solid(&self) -> bool {
-        *self != BlockType::NoBlock
+        self.properties().solid
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.properties().transparent
+    }
+
+    /// whether the colour of the block is not fully opaque, translucent blocks are drawn after all others with blending
+    pub fn is_translucent(&self) -> bool {
+        self.color().is_some_and(|[_, _, _, a]| a < 1.0)
+    }
+
+    pub fn light_emission(&self) -> u8 {
+        self.properties().light_emission
+    }
+
+    pub fn hardness(&self) -> f32 {
+        self.properties().hardness
+    }
+
+    pub fn has_collision(&self) -> bool {
+        self.properties().collision
+    }
+
+    /// numeric id of the block type, as it is stored on disk
+    pub fn id(&self) -> u16 {
+        self.0
+    }
+}
+
+impl TryFrom<u16> for BlockType {
+    type Error = WorldError;
+
+    /// succeeds if `id` is registered
+    fn try_from(id: u16) -> Result<Self, Self::Error> {
+        let block = BlockType(id);
+        match registry().get(block) {
+            Some(_) => Ok(block),
+            None => Err(WorldError::InvalidBlockId(id))
+        }
     }
 }
@@ -0,0 +1,33 @@
+use std::fmt;
+use crate::config;
+use super::icoords::ICoords;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorldError {
+    /// the segment containing the coordinates has not been generated or loaded
+    Unloaded(ICoords),
+    /// the coordinates lie outside of the range addressable by the world, see `ICoords::in_range`
+    OutOfRange(ICoords),
+    /// the numeric id does not belong to any block type
+    InvalidBlockId(u16),
+    /// the region has more blocks than a single edit may change, see `config::MAX_EDIT_VOLUME`.
+    /// `None` if the number of blocks does not even fit into a u64.
+    TooLarge(Option<u64>),
+    /// the edit would need the given number of bytes of undo history, more than its memory limit
+    HistoryFull(usize),
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldError::Unloaded(c) => write!(f, "segment containing ({}, {}, {}) is not loaded", c.x, c.y, c.z),
+            WorldError::OutOfRange(c) => write!(f, "coordinates ({}, {}, {}) are out of range", c.x, c.y, c.z),
+            WorldError::InvalidBlockId(id) => write!(f, "invalid block id {}", id),
+            WorldError::TooLarge(Some(volume)) => write!(f, "region of {} blocks is larger than the limit of {}", volume, config::MAX_EDIT_VOLUME),
+            WorldError::TooLarge(None) => write!(f, "region is larger than the limit of {} blocks", config::MAX_EDIT_VOLUME),
+            WorldError::HistoryFull(bytes) => write!(f, "the edit would need {} KiB of undo history, more than its memory limit", bytes / 1024),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
@@ -0,0 +1,43 @@
+use glam::Vec3;
+use crate::profiler::*;
+use super::noise::*;
+use super::icoords::ICoords;
+use super::block::BlockType;
+use super::segment::{L1Segment, L1_SIZE_BL};
+
+/// range of the horizontal shift of the noise by the seed, in blocks
+const SEED_SHIFT_RANGE: i64 = 1 << 20;
+
+/// where the terrain of `seed` lies in the noise. seed 0 is not shifted.
+fn seed_shift(seed: u32) -> ICoords {
+    let seed = seed as i64;
+    ICoords::new(
+        seed.wrapping_mul(0x9e37_79b9) % SEED_SHIFT_RANGE,
+        0,
+        seed.wrapping_mul(0x85eb_ca6b) % SEED_SHIFT_RANGE,
+    )
+}
+
+/// the continuous terrain density of the world generated with `seed` at the block `coords`,
+/// the block is solid where the density is positive
+pub fn density(seed: u32, coords: ICoords) -> f32 {
+    let c = coords + seed_shift(seed);
+    perlin(Vec3::new(c.x as f32 / 256., c.y as f32 / 32., c.z as f32 / 256.))
+}
+
+/// generates the terrain of a single l1 segment. only depends on `seed` and `coords`, so it can run on any thread.
+/// * `coords` - coordinates of the 0 0 0 block in the desired l1_segment
+pub fn generate_l1_segment(seed: u32, coords: ICoords) -> L1Segment {
+    p_start("generate_l1_segment");
+    let mut l1_seg = L1Segment::default();
+    let grass = BlockType::from_name("grass").expect("block type `grass` is not registered");
+
+    for delta in L1_SIZE_BL {
+        let v = density(seed, coords + delta);
+        if v > 0. {
+            l1_seg.set(delta, grass.into());
+        }
+    }
+    p_end("generate_l1_segment");
+    l1_seg
+}
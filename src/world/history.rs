@@ -0,0 +1,399 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use super::error::WorldError;
+use super::icoords::ICoords;
+use super::palette::PalettedVec;
+use super::segment::L1Segment;
+use super::state::BlockState;
+
+// bytes counted for every entry of a sparse image, including the overhead of the hash map
+const SPARSE_ENTRY_SIZE: usize = 2 * std::mem::size_of::<(usize, BlockState)>();
+
+/// the bytes that an image of all blocks of `seg` takes in an operation, see `History::record_image`
+pub fn image_size(seg: &L1Segment) -> usize {
+    std::mem::size_of::<(ICoords, SegmentImage)>() + seg.blocks_memory_usage()
+}
+
+/// the blocks of an l1 segment before an operation changed them
+#[derive(Clone, Debug)]
+enum SegmentImage {
+    /// the previous blocks at the changed indices, for operations that only change a few blocks of the segment
+    Sparse(HashMap<usize, BlockState>),
+    /// all previous blocks of the segment
+    Full(PalettedVec<BlockState>),
+}
+
+impl SegmentImage {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<(ICoords, Self)>() + match self {
+            SegmentImage::Sparse(blocks) => blocks.capacity() * SPARSE_ENTRY_SIZE,
+            SegmentImage::Full(blocks) => blocks.memory_usage(),
+        }
+    }
+
+    /// exchanges the blocks of the image with those of `seg`, which turns an undo image into a redo image and vice versa
+    fn swap(&mut self, seg: &mut L1Segment) {
+        match self {
+            SegmentImage::Sparse(blocks) => {
+                for (&i, block) in blocks.iter_mut() {
+                    *block = seg.set_index(i, *block);
+                }
+            },
+            SegmentImage::Full(blocks) => seg.swap_blocks(blocks),
+        }
+    }
+}
+
+/// a named group of block changes that is undone and redone as a whole.
+/// it stores one image per changed l1 segment, so its size is bounded by the size of the segments and not by the number of changes.
+#[derive(Clone, Debug)]
+pub struct Operation {
+    pub name: String,
+    segments: HashMap<ICoords, SegmentImage>,
+    memory_usage: usize,
+}
+
+impl Operation {
+    fn new(name: &str) -> Self {
+        Operation {
+            name: String::from(name),
+            segments: HashMap::new(),
+            memory_usage: std::mem::size_of::<Self>() + name.len(),
+        }
+    }
+
+    /// an upper bound of the bytes that `record_block` and a following `record_image` add for the segment at `l1_glob`
+    /// - `full_size`: the memory usage of the blocks of the segment
+    fn record_bound(&self, l1_glob: ICoords, full_size: usize) -> usize {
+        match self.segments.get(&l1_glob) {
+            Some(SegmentImage::Full(_)) => 0,
+            // the map may grow once more before it is converted into a full image
+            Some(SegmentImage::Sparse(blocks)) => full_size + (2 * blocks.capacity() + 4) * SPARSE_ENTRY_SIZE,
+            None => full_size + std::mem::size_of::<(ICoords, SegmentImage)>() + 4 * SPARSE_ENTRY_SIZE,
+        }
+    }
+
+    /// see `History::record_block`
+    fn record_block(&mut self, l1_glob: ICoords, index: usize, before: BlockState, full_size: usize) -> bool {
+        let (image, old_usage) = match self.segments.entry(l1_glob) {
+            Entry::Occupied(entry) => {
+                let usage = entry.get().memory_usage();
+                (entry.into_mut(), usage)
+            },
+            Entry::Vacant(entry) => (entry.insert(SegmentImage::Sparse(HashMap::new())), 0),
+        };
+        let SegmentImage::Sparse(blocks) = image else {
+            return false;
+        };
+        // the first change of a block holds its state before the operation
+        blocks.entry(index).or_insert(before);
+        let new_usage = image.memory_usage();
+        self.memory_usage = self.memory_usage + new_usage - old_usage;
+        new_usage > full_size
+    }
+
+    /// see `History::record_image`
+    fn record_image(&mut self, l1_glob: ICoords, mut blocks: PalettedVec<BlockState>) {
+        let old_usage = match self.segments.get(&l1_glob) {
+            Some(SegmentImage::Full(_)) => return,
+            Some(image @ SegmentImage::Sparse(changed)) => {
+                for (&i, &block) in changed {
+                    blocks.set(i, block);
+                }
+                image.memory_usage()
+            },
+            None => 0,
+        };
+        let image = SegmentImage::Full(blocks);
+        self.memory_usage = self.memory_usage + image.memory_usage() - old_usage;
+        self.segments.insert(l1_glob, image);
+    }
+
+    /// global coordinates of the l1 segments changed by the operation
+    pub fn segments(&self) -> impl Iterator<Item = ICoords> + '_ {
+        self.segments.keys().copied()
+    }
+
+    /// exchanges the recorded blocks of the segment at `l1_glob` with those of `seg`.
+    /// swapping all segments of the operation undoes it, swapping them again redoes it.
+    pub fn swap(&mut self, l1_glob: ICoords, seg: &mut L1Segment) {
+        if let Some(image) = self.segments.get_mut(&l1_glob) {
+            let old_usage = image.memory_usage();
+            image.swap(seg);
+            self.memory_usage = self.memory_usage + image.memory_usage() - old_usage;
+        }
+    }
+
+    /// approximate number of bytes used by the operation
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+}
+
+/// undo and redo stacks of block changes.
+///
+/// changes are recorded into the open operation, see `begin`. space for them has to be reserved first, which drops the oldest operations
+/// once the history would use more than `memory_limit` bytes, or fails if the open operation alone would exceed it.
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<Operation>,
+    redo: Vec<Operation>,
+    current: Option<Operation>,
+    memory_limit: usize,
+    // bytes used by the operations on the undo and redo stacks
+    memory_usage: usize,
+}
+
+impl History {
+    pub fn new(memory_limit: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            current: None,
+            memory_limit,
+            memory_usage: 0,
+        }
+    }
+
+    /// opens a new operation called `name`. an operation that is still open is ended first.
+    pub fn begin(&mut self, name: &str) {
+        self.end();
+        self.current = Some(Operation::new(name));
+    }
+
+    /// closes the open operation and pushes it onto the undo stack, unless it is empty
+    pub fn end(&mut self) {
+        if let Some(operation) = self.current.take() {
+            if operation.segments.is_empty() {
+                return;
+            }
+            self.memory_usage += operation.memory_usage();
+            self.undo.push_back(operation);
+        }
+    }
+
+    /// whether an operation is open
+    pub fn is_recording(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// makes room for `bytes` more bytes in the open operation. the redo stack is dropped, as the change makes it invalid,
+    /// as well as the oldest operations as long as the limit would be exceeded.
+    /// fails without dropping anything if the open operation alone would exceed the limit, the change must not be made then.
+    pub fn reserve(&mut self, bytes: usize) -> Result<(), WorldError> {
+        let needed = self.current.as_ref().map_or(0, |o| o.memory_usage()).saturating_add(bytes);
+        if needed > self.memory_limit {
+            return Err(WorldError::HistoryFull(needed));
+        }
+        for operation in self.redo.drain(..) {
+            self.memory_usage -= operation.memory_usage();
+        }
+        self.drop_oldest(needed);
+        Ok(())
+    }
+
+    /// drops the oldest operations until `additional` more bytes fit into the limit
+    fn drop_oldest(&mut self, additional: usize) {
+        while self.memory_usage + additional > self.memory_limit {
+            let Some(dropped) = self.undo.pop_front() else {
+                break;
+            };
+            self.memory_usage -= dropped.memory_usage();
+        }
+    }
+
+    /// records the block `before` at `index` of the l1 segment at `l1_glob`, which is about to be changed.
+    /// the space is reserved before recording.
+    /// - `full_size`: the memory usage of the blocks of the segment, see `L1Segment::blocks_memory_usage`
+    ///
+    /// returns whether an image of the whole segment takes less space than the changed blocks recorded so far,
+    /// the caller should then pass the blocks of the segment, as they are before the change, to `record_image`
+    pub fn record_block(&mut self, l1_glob: ICoords, index: usize, before: BlockState, full_size: usize) -> Result<bool, WorldError> {
+        let bound = self.current.get_or_insert_with(|| Operation::new("set block")).record_bound(l1_glob, full_size);
+        self.reserve(bound)?;
+        let operation = self.current.as_mut().unwrap();
+        Ok(operation.record_block(l1_glob, index, before, full_size))
+    }
+
+    /// records all blocks of the l1 segment at `l1_glob` as they are before the changes of the open operation.
+    /// blocks that have already been recorded keep their recorded state.
+    /// the caller has to reserve the space first, see `reserve`.
+    pub fn record_image(&mut self, l1_glob: ICoords, blocks: PalettedVec<BlockState>) {
+        let operation = self.current.get_or_insert_with(|| Operation::new("set block"));
+        operation.record_image(l1_glob, blocks);
+    }
+
+    /// ends the open operation and takes the latest operation from the undo stack.
+    /// the caller has to swap its segments (see `Operation::swap`) and pass it to `push_undone`.
+    pub fn take_undo(&mut self) -> Option<Operation> {
+        self.end();
+        let operation = self.undo.pop_back()?;
+        self.memory_usage -= operation.memory_usage();
+        Some(operation)
+    }
+
+    pub fn push_undone(&mut self, operation: Operation) {
+        self.drop_oldest(operation.memory_usage());
+        self.memory_usage += operation.memory_usage();
+        self.redo.push(operation);
+    }
+
+    /// ends the open operation and takes the latest undone operation from the redo stack.
+    /// the caller has to swap its segments (see `Operation::swap`) and pass it to `push_redone`.
+    pub fn take_redo(&mut self) -> Option<Operation> {
+        self.end();
+        let operation = self.redo.pop()?;
+        self.memory_usage -= operation.memory_usage();
+        Some(operation)
+    }
+
+    pub fn push_redone(&mut self, operation: Operation) {
+        self.drop_oldest(operation.memory_usage());
+        self.memory_usage += operation.memory_usage();
+        self.undo.push_back(operation);
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.current = None;
+        self.memory_usage = 0;
+    }
+
+    pub fn n_undo(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn n_redo(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// approximate number of bytes used by all operations, including the open one
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage + self.current.as_ref().map_or(0, |o| o.memory_usage())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::World;
+    use crate::world::block::BlockType;
+    use crate::world::segment::L1_SIZE_BL;
+
+    fn block(name: &str) -> BlockState {
+        BlockState::new(BlockType::from_name(name).unwrap())
+    }
+
+    /// a world whose l1 segments at x = 0 to `n` - 1 are a checkerboard of stone and glass, with an empty history limited to `limit` bytes
+    fn boards(n: i64, limit: usize) -> World {
+        let mut board = L1Segment::default();
+        for i in 0..L1_SIZE_BL.volume() as usize {
+            board.set_index(i, block(["stone", "glass"][i % 2]));
+        }
+        let mut world = World::new();
+        for x in 0..n {
+            world.insert_l1_segment(ICoords::new(x * 32, 0, 0), board.clone());
+        }
+        world.history = History::new(limit);
+        world
+    }
+
+    /// sets the first `n` blocks of the l1 segment at `l1_glob` to dirt, returns the coordinates of the block that could not be set
+    fn dirt(world: &mut World, l1_glob: ICoords, n: u64) -> Result<(), (ICoords, WorldError)> {
+        for i in 0..n {
+            let coords = l1_glob * L1_SIZE_BL.into() + L1_SIZE_BL.c3d(i);
+            world.set_block(coords, block("dirt")).map_err(|e| (coords, e))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn undo_and_redo_restore_sparse_and_full_images() {
+        let mut world = boards(2, usize::MAX);
+        let (a, b) = (ICoords::new(0, 0, 0), ICoords::new(1, 0, 0));
+        let board = |i: u64| block(["stone", "glass"][i as usize % 2]);
+
+        world.begin_operation("sparse");
+        dirt(&mut world, b, 3).unwrap();
+        dirt(&mut world, b, 2).unwrap();
+        world.end_operation();
+        let sparse_usage = world.history().memory_usage();
+        assert!(sparse_usage < 1024, "{}", sparse_usage);
+
+        world.begin_operation("full");
+        dirt(&mut world, a, 4000).unwrap();
+        world.set_block(ICoords::new(-1, 0, 0), block("stone")).unwrap();
+        world.end_operation();
+        // the changes are stored as an image of the segment with 2 bits per block instead of 4000 single blocks
+        let full_usage = world.history().memory_usage() - sparse_usage;
+        assert!(full_usage < 12 * 1024, "{}", full_usage);
+        assert_eq!(world.history().n_undo(), 2);
+
+        assert_eq!(world.undo().as_deref(), Some("full"));
+        for i in [0, 1, 3999, 4000] {
+            assert_eq!(world.get_block(L1_SIZE_BL.c3d(i)), board(i), "{}", i);
+        }
+        assert_eq!(world.get_block(ICoords::new(-1, 0, 0)), BlockState::NO_BLOCK);
+        assert_eq!(world.redo().as_deref(), Some("full"));
+        assert_eq!(world.get_block(L1_SIZE_BL.c3d(3999)), block("dirt"));
+        assert_eq!(world.get_block(L1_SIZE_BL.c3d(4000)), board(4000));
+        assert_eq!(world.get_block(ICoords::new(-1, 0, 0)), block("stone"));
+
+        assert_eq!(world.undo().as_deref(), Some("full"));
+        assert_eq!(world.undo().as_deref(), Some("sparse"));
+        for i in 0..4 {
+            assert_eq!(world.get_block(ICoords::new(32, 0, 0) + L1_SIZE_BL.c3d(i)), board(i), "{}", i);
+        }
+        assert_eq!(world.undo(), None);
+        assert_eq!(world.redo().as_deref(), Some("sparse"));
+        assert_eq!(world.get_block(ICoords::new(32, 0, 0) + L1_SIZE_BL.c3d(2)), block("dirt"));
+        // a new change drops the redo stack
+        world.set_block(ICoords::new(5, 5, 5), block("stone")).unwrap();
+        assert_eq!(world.history().n_undo(), 2);
+        assert_eq!(world.redo(), None);
+    }
+
+    #[test]
+    fn the_oldest_operations_are_dropped_beyond_the_limit() {
+        let limit = 48 * 1024;
+        let mut world = boards(8, limit);
+        for x in 0..8 {
+            world.begin_operation("dirt");
+            dirt(&mut world, ICoords::new(x, 0, 0), 1000).unwrap();
+            world.end_operation();
+            assert!(world.history().memory_usage() <= limit);
+        }
+        let n_undo = world.history().n_undo();
+        assert!(n_undo > 1 && n_undo < 8, "{}", n_undo);
+        for _ in 0..n_undo {
+            world.undo().unwrap();
+            assert!(world.history().memory_usage() <= limit);
+        }
+        assert_eq!(world.undo(), None);
+        assert_eq!(world.get_block(ICoords::new(7 * 32, 0, 0)), block("stone"));
+        assert_eq!(world.get_block(ICoords::new(0, 0, 0)), block("dirt"));
+    }
+
+    #[test]
+    fn edits_beyond_the_limit_are_refused() {
+        let limit = 48 * 1024;
+        let mut world = boards(8, limit);
+        world.begin_operation("dirt");
+        let error = (0..8).map(|x| dirt(&mut world, ICoords::new(x, 0, 0), 1000)).find_map(Result::err);
+        world.end_operation();
+        let Some((refused, WorldError::HistoryFull(needed))) = error else {
+            panic!("expected the history to be full, got {:?}", error);
+        };
+        assert!(needed > limit);
+        assert!(world.history().memory_usage() <= limit);
+
+        // the refused change has not been made and everything before it can be undone
+        assert_ne!(world.get_block(refused), block("dirt"));
+        assert_eq!(world.get_block(ICoords::new(0, 0, 0)), block("dirt"));
+        assert_eq!(world.undo().as_deref(), Some("dirt"));
+        for x in 0..8 {
+            assert_eq!(world.get_block(ICoords::new(x * 32, 0, 0)), block("stone"), "{}", x);
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use std::ops::{Add, Mul, Div, Rem};
+use std::ops::{Add, Sub, Mul, Div, Rem};
 use glam::Vec3;
 use super::Axis;
 use super::*;
 
 
+/// largest absolute block coordinate that can be addressed in the world.
+/// block positions are converted to `f32` for meshing, which represents integers exactly only up to 2^24.
+pub const MAX_COORD: i64 = 1 << 24;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ICoords {
     pub x: i64,
@@ -18,6 +22,13 @@ impl Add for ICoords {
     }
 }
 
+impl Sub for ICoords {
+    type Output = Self;
+    fn sub(self, rhs: ICoords) -> ICoords {
+        ICoords { x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z }
+    }
+}
+
 impl Mul for ICoords {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self {
@@ -44,6 +55,13 @@ impl ICoords {
         Self { x, y, z }
     }
 
+    /// whether the block at self lies within `MAX_COORD` on all axes
+    pub fn in_range(&self) -> bool {
+        (-MAX_COORD..=MAX_COORD).contains(&self.x)
+        && (-MAX_COORD..=MAX_COORD).contains(&self.y)
+        && (-MAX_COORD..=MAX_COORD).contains(&self.z)
+    }
+
     pub fn triple(&self) -> (i64, i64, i64) {
         (self.x, self.y, self.z)
     }
@@ -56,26 +74,28 @@ impl ICoords {
         Self { x: v.x.floor() as i64, y: v.y.floor() as i64, z: v.z.floor() as i64 }
     }
 
-    /// for a given world coordinate, find the coordinates of the respective L3, L2 and L1 segments that contain this coordinate
+    /// for a given world coordinate, find the local coordinates of the respective L2 and L1 segments and the block that contain this coordinate
     /// - `along`: the axis that should be decomposed
+    ///
+    /// all divisions round towards negative infinity, so that e.g. x = -1 lies in the last block of the L1 segment at x = -1
     pub fn decompose(&self, along: Axis) -> (i64, i64, i64) {
         match along {
             Axis::X => {
-                let l2c = (self.x / L2_SIZE_BL.x as i64) % L3_SIZE.x as i64;
-                let l1c = (self.x / L1_SIZE_BL.x as i64) % L2_SIZE.x as i64;
-                let bc = self.x % L1_SIZE.x as i64;
+                let l2c = self.x.div_euclid(L2_SIZE_BL.x as i64).rem_euclid(L3_SIZE.x as i64);
+                let l1c = self.x.div_euclid(L1_SIZE_BL.x as i64).rem_euclid(L2_SIZE.x as i64);
+                let bc = self.x.rem_euclid(L1_SIZE.x as i64);
                 return (l2c, l1c, bc);
             }
             Axis::Y => {
-                let l2c = (self.y / L2_SIZE_BL.y as i64) % L3_SIZE.y as i64;
-                let l1c = (self.y / L1_SIZE_BL.y as i64) % L2_SIZE.y as i64;
-                let bc = self.y % L1_SIZE.y as i64;
+                let l2c = self.y.div_euclid(L2_SIZE_BL.y as i64).rem_euclid(L3_SIZE.y as i64);
+                let l1c = self.y.div_euclid(L1_SIZE_BL.y as i64).rem_euclid(L2_SIZE.y as i64);
+                let bc = self.y.rem_euclid(L1_SIZE.y as i64);
                 return (l2c, l1c, bc);
             }
             Axis::Z => {
-                let l2c = (self.z / L2_SIZE_BL.z as i64) % L3_SIZE.z as i64;
-                let l1c = (self.z / L1_SIZE_BL.z as i64) % L2_SIZE.z as i64;
-                let bc = self.z % L1_SIZE.z as i64;
+                let l2c = self.z.div_euclid(L2_SIZE_BL.z as i64).rem_euclid(L3_SIZE.z as i64);
+                let l1c = self.z.div_euclid(L1_SIZE_BL.z as i64).rem_euclid(L2_SIZE.z as i64);
+                let bc = self.z.rem_euclid(L1_SIZE.z as i64);
                 return (l2c, l1c, bc);
             }
         }
@@ -129,3 +149,49 @@ impl ICoords {
         ICoords {x, y, z}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `c` placed on the axis `axis` (0: x, 1: y, 2: z), the other coordinates are 0
+    fn on_axis(axis: usize, c: i64) -> ICoords {
+        let mut v = [0; 3];
+        v[axis] = c;
+        ICoords::new(v[0], v[1], v[2])
+    }
+
+    #[test]
+    fn segment_boundaries_around_zero() {
+        // (block coordinate, l1 segment, block within the l1 segment, l3 segment)
+        let cases = [
+            (-33, -2, 31, -1),
+            (-32, -1, 0, -1),
+            (-1, -1, 31, -1),
+            (0, 0, 0, 0),
+            (31, 0, 31, 0),
+            (32, 1, 0, 0),
+        ];
+        for axis in 0..3 {
+            for (c, l1, bl, l3) in cases {
+                let coords = on_axis(axis, c);
+                assert_eq!(coords.l1_glob(), on_axis(axis, l1), "l1_glob of {:?}", coords);
+                assert_eq!(coords.bl_loc(), on_axis(axis, bl), "bl_loc of {:?}", coords);
+                assert_eq!(coords.l3_glob(), on_axis(axis, l3), "l3_glob of {:?}", coords);
+                // the segment and the position within it add up to the block again
+                assert_eq!(coords.l1_glob() * L1_SIZE_BL.into() + coords.bl_loc(), coords);
+            }
+        }
+    }
+
+    #[test]
+    fn local_segments_wrap_below_zero() {
+        let last = ICoords::new(-1, -1, -1);
+        let n2 = L2_SIZE.x as i64 - 1;
+        let n3 = L3_SIZE.x as i64 - 1;
+        assert_eq!(last.l1_loc(), ICoords::new(n2, n2, n2));
+        assert_eq!(last.l2_loc(), ICoords::new(n3, n3, n3));
+        assert_eq!(last.decompose(Axis::Y), (n3, n2, 31));
+        assert_eq!(ICoords::new(0, 0, 0).decompose(Axis::Z), (0, 0, 0));
+    }
+}
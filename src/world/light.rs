@@ -0,0 +1,385 @@
+// light is stored per block as two 4 bit levels:
+// - sky light enters at full strength at the top of every loaded column and travels down without losing strength
+//   until it hits an opaque block. from there it spreads like block light.
+// - block light starts at the emission of a block and decreases by one per block it spreads.
+//
+// segments are lit completely when they are inserted, later block changes only relight the affected area
+// by first removing the light that came from the changed blocks and then spreading the remaining light again.
+// edits of whole regions instead relight each touched segment at once: its light is removed from its faces
+// outwards and it is lit like a new segment.
+
+use std::collections::VecDeque;
+use super::*;
+use super::size::Size2D;
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// brightness of a block that receives no light at all
+const MIN_BRIGHTNESS: f32 = 0.1;
+/// brightness lost per light level below `MAX_LIGHT`
+const FALLOFF: f32 = 0.8;
+
+pub fn pack(sky: u8, block: u8) -> u8 {
+    (sky << 4) | block
+}
+
+pub fn sky_light(light: u8) -> u8 {
+    light >> 4
+}
+
+pub fn block_light(light: u8) -> u8 {
+    light & 0xf
+}
+
+/// factor for the colour of a face that is lit by the packed `light`
+pub fn brightness(light: u8) -> f32 {
+    let level = sky_light(light).max(block_light(light));
+    FALLOFF.powi((MAX_LIGHT - level) as i32).max(MIN_BRIGHTNESS)
+}
+
+/// whether replacing `before` by `after` can change the light of any block
+pub(super) fn affects_light(before: BlockState, after: BlockState) -> bool {
+    before.is_opaque() != after.is_opaque() || before.block.light_emission() != after.block.light_emission()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    fn get(self, light: u8) -> u8 {
+        match self {
+            Channel::Sky => sky_light(light),
+            Channel::Block => block_light(light),
+        }
+    }
+
+    fn with(self, light: u8, level: u8) -> u8 {
+        match self {
+            Channel::Sky => pack(level, block_light(light)),
+            Channel::Block => pack(sky_light(light), level),
+        }
+    }
+
+    /// the level that a block lit with `level` passes on to its neighbour in direction `face`
+    fn spread(self, level: u8, face: Face) -> u8 {
+        if self == Channel::Sky && face == Face::YNeg && level == MAX_LIGHT {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// local coordinates of the blocks of an l1 segment that touch its `face`
+fn face_blocks(face: Face) -> impl Iterator<Item = ICoords> {
+    let size: ICoords = L1_SIZE_BL.into();
+    let last = size - ICoords::new(1, 1, 1);
+    let plane = match face {
+        Face::XPos | Face::XNeg => Size2D { x: L1_SIZE_BL.y, y: L1_SIZE_BL.z },
+        Face::YPos | Face::YNeg => Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.z },
+        Face::ZPos | Face::ZNeg => Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.y },
+    };
+    plane.into_iter().map(move |(a, b)| match face {
+        Face::XPos => ICoords::new(last.x, a, b),
+        Face::XNeg => ICoords::new(0, a, b),
+        Face::YPos => ICoords::new(a, last.y, b),
+        Face::YNeg => ICoords::new(a, 0, b),
+        Face::ZPos => ICoords::new(a, b, last.z),
+        Face::ZNeg => ICoords::new(a, b, 0),
+    })
+}
+
+impl World {
+    /// the packed light level at `coords`, see `sky_light` and `block_light`. unloaded blocks are dark.
+    pub fn light(&self, coords: ICoords) -> u8 {
+        self.l1_segment(coords).map_or(0, |seg| seg.light(coords.bl_loc()))
+    }
+
+    fn light_level(&self, channel: Channel, coords: ICoords) -> Option<u8> {
+        if !coords.in_range() {
+            return None;
+        }
+        self.l1_segment(coords).map(|seg| channel.get(seg.light(coords.bl_loc())))
+    }
+
+    fn set_light_level(&mut self, channel: Channel, coords: ICoords, level: u8) {
+        let Some(seg) = self.l1_segment_mut(coords) else {
+            return;
+        };
+        let local = coords.bl_loc();
+        let light = seg.light(local);
+        seg.set_light(local, channel.with(light, level));
+        self.light_changed.extend(touching_l1_segments(coords));
+    }
+
+    /// computes the light of the l1 segment at global segment coordinates `l1_glob` from its blocks and
+    /// the light of its neighbours, then spreads its light into the neighbours.
+    /// sky light enters at the top unless the segment above is loaded.
+    pub(super) fn light_l1_segment(&mut self, l1_glob: ICoords) {
+        let size: ICoords = L1_SIZE_BL.into();
+        let origin = l1_glob * size;
+        let Some(seg) = self.l1_segment(origin) else {
+            return;
+        };
+        let n = L1_SIZE_BL.volume() as usize;
+        let (opaque, emission): (Vec<bool>, Vec<u8>) = match seg.uniform() {
+            Some(block) => (vec![block.is_opaque(); n], vec![block.block.light_emission(); n]),
+            None => seg.blocks().map(|b| (b.is_opaque(), b.block.light_emission())).unzip(),
+        };
+        let neighbours = Face::all().map(|face| self.l1_segment(origin + face.numeric() * size));
+
+        // the segment is lit on its own first, which avoids a hash map lookup per block
+        let levels = [Channel::Sky, Channel::Block].map(|channel| {
+            let mut levels = vec![0u8; n];
+            let mut queue = VecDeque::new();
+            match channel {
+                Channel::Sky => {
+                    for (x, z) in (Size2D { x: L1_SIZE_BL.x, y: L1_SIZE_BL.z }) {
+                        if let Some(above) = neighbours[Face::YPos as usize] {
+                            if sky_light(above.light(ICoords::new(x, 0, z))) != MAX_LIGHT {
+                                continue;
+                            }
+                        }
+                        for y in (0..size.y).rev() {
+                            let i = L1_SIZE_BL.c1d(ICoords::new(x, y, z)) as usize;
+                            if opaque[i] {
+                                break;
+                            }
+                            levels[i] = MAX_LIGHT;
+                            queue.push_back(i);
+                        }
+                    }
+                },
+                Channel::Block => {
+                    for i in (0..n).filter(|&i| emission[i] > 0) {
+                        levels[i] = emission[i];
+                        queue.push_back(i);
+                    }
+                },
+            }
+
+            for face in Face::all() {
+                let Some(neighbour) = neighbours[face as usize] else {
+                    continue;
+                };
+                for local in face_blocks(face) {
+                    let i = L1_SIZE_BL.c1d(local) as usize;
+                    let outside = (origin + local + face.numeric()).bl_loc();
+                    let level = channel.spread(channel.get(neighbour.light(outside)), face.opposite());
+                    if !opaque[i] && level > levels[i] {
+                        levels[i] = level;
+                        queue.push_back(i);
+                    }
+                }
+            }
+
+            while let Some(i) = queue.pop_front() {
+                let c = L1_SIZE_BL.c3d(i as u64);
+                for face in Face::all() {
+                    let target = c + face.numeric();
+                    if !L1_SIZE_BL.contains(target) {
+                        continue;
+                    }
+                    let j = L1_SIZE_BL.c1d(target) as usize;
+                    let level = channel.spread(levels[i], face);
+                    if !opaque[j] && level > levels[j] {
+                        levels[j] = level;
+                        queue.push_back(j);
+                    }
+                }
+            }
+            levels
+        });
+        let [sky, block] = levels;
+        let packed: Vec<u8> = sky.iter().zip(&block).map(|(&s, &b)| pack(s, b)).collect();
+        let loaded = neighbours.map(|n| n.is_some());
+
+        self.l1_segment_mut(origin).unwrap().set_lights(&packed);
+        self.light_changed.insert(l1_glob);
+
+        for channel in [Channel::Sky, Channel::Block] {
+            // the segment below was lit from the top of its column, which is now covered
+            let mut removal = VecDeque::new();
+            if channel == Channel::Sky && loaded[Face::YNeg as usize] {
+                for local in face_blocks(Face::YNeg) {
+                    let below = origin + local + Face::YNeg.numeric();
+                    let i = L1_SIZE_BL.c1d(local) as usize;
+                    if sky[i] != MAX_LIGHT && self.light_level(channel, below) == Some(MAX_LIGHT) {
+                        self.set_light_level(channel, below, 0);
+                        removal.push_back((below, MAX_LIGHT));
+                    }
+                }
+            }
+            let mut queue = self.remove_light(channel, removal);
+            for face in Face::all().into_iter().filter(|&f| loaded[f as usize]) {
+                queue.extend(face_blocks(face).map(|local| origin + local));
+            }
+            self.propagate_light(channel, queue);
+        }
+    }
+
+    /// updates the light after many blocks of the l1 segments at global segment coordinates `segments` changed
+    pub(super) fn relight_l1_segments(&mut self, mut segments: Vec<ICoords>) {
+        let size: ICoords = L1_SIZE_BL.into();
+        // all light leaving the segments passes their faces
+        let mut faces = Vec::new();
+        for &l1_glob in &segments {
+            let origin = l1_glob * size;
+            let Some(seg) = self.l1_segment_mut(origin) else {
+                continue;
+            };
+            faces.extend(Face::all().into_iter().flat_map(face_blocks).map(|local| (origin + local, seg.light(local))));
+            seg.set_lights(&vec![0; L1_SIZE_BL.volume() as usize]);
+        }
+        for channel in [Channel::Sky, Channel::Block] {
+            let removal = faces.iter()
+                .map(|&(c, light)| (c, channel.get(light)))
+                .filter(|&(_, level)| level > 0)
+                .collect();
+            let queue = self.remove_light(channel, removal);
+            self.propagate_light(channel, queue);
+        }
+
+        // like on load, top down so that sky light passes through each column only once
+        segments.sort_by_key(|c| -c.y);
+        for l1_glob in segments {
+            self.light_l1_segment(l1_glob);
+        }
+    }
+
+    /// updates the light after the blocks at `positions` changed
+    pub(super) fn relight(&mut self, positions: &[ICoords]) {
+        for channel in [Channel::Sky, Channel::Block] {
+            let mut removal = VecDeque::new();
+            for &c in positions {
+                if let Some(level) = self.light_level(channel, c) {
+                    self.set_light_level(channel, c, 0);
+                    removal.push_back((c, level));
+                }
+            }
+            let mut queue = self.remove_light(channel, removal);
+
+            for &c in positions {
+                let block = self.get_block(c);
+                let source = match channel {
+                    Channel::Sky => {
+                        let top = c.bl_loc().y == L1_SIZE_BL.y as i64 - 1;
+                        let covered = self.l1_segment(c + Face::YPos.numeric()).is_some();
+                        if top && !covered && !block.is_opaque() { MAX_LIGHT } else { 0 }
+                    },
+                    Channel::Block => block.block.light_emission(),
+                };
+                if source > 0 {
+                    self.set_light_level(channel, c, source);
+                }
+                queue.push_back(c);
+            }
+            self.propagate_light(channel, queue);
+        }
+    }
+
+    /// removes the light of `channel` that spread from the blocks in `queue`, given with their level before they were darkened.
+    /// returns the blocks whose light has to be spread again to fill the darkened area.
+    fn remove_light(&mut self, channel: Channel, mut queue: VecDeque<(ICoords, u8)>) -> VecDeque<ICoords> {
+        let mut relight = VecDeque::new();
+        while let Some((c, level)) = queue.pop_front() {
+            for face in Face::all() {
+                let target = c + face.numeric();
+                let Some(target_level) = self.light_level(channel, target) else {
+                    continue;
+                };
+                if target_level == 0 {
+                    continue;
+                }
+                if target_level < level || (target_level == MAX_LIGHT && channel.spread(level, face) == MAX_LIGHT) {
+                    // emitting blocks keep their own light
+                    let emission = match channel {
+                        Channel::Sky => 0,
+                        Channel::Block => self.get_block(target).block.light_emission(),
+                    };
+                    self.set_light_level(channel, target, emission);
+                    queue.push_back((target, target_level));
+                    if emission > 0 {
+                        relight.push_back(target);
+                    }
+                } else {
+                    relight.push_back(target);
+                }
+            }
+        }
+        relight
+    }
+
+    /// spreads the light of `channel` from the blocks in `queue` until no block gets any brighter
+    fn propagate_light(&mut self, channel: Channel, mut queue: VecDeque<ICoords>) {
+        while let Some(c) = queue.pop_front() {
+            let Some(level) = self.light_level(channel, c) else {
+                continue;
+            };
+            if level <= 1 {
+                continue;
+            }
+            for face in Face::all() {
+                let target = c + face.numeric();
+                let spread = channel.spread(level, face);
+                match self.light_level(channel, target) {
+                    Some(target_level) if target_level < spread && !self.get_block(target).is_opaque() => {
+                        self.set_light_level(channel, target, spread);
+                        queue.push_back(target);
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::region::Region;
+
+    /// compares the light of every block of `world` with the light of a copy that is lit from scratch
+    fn assert_lit_like_fresh(world: &World) {
+        let size: ICoords = L1_SIZE_BL.into();
+        let mut segments = world.l1_segment_coords();
+        segments.sort_by_key(|c| -c.y);
+        let mut fresh = World::new();
+        for &l1_glob in &segments {
+            fresh.insert_l1_segment(l1_glob * size, world.l1_segment(l1_glob * size).unwrap().clone());
+        }
+        for l1_glob in segments {
+            for local in L1_SIZE_BL {
+                let c = l1_glob * size + local;
+                assert_eq!(world.light(c), fresh.light(c), "{:?}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn region_edits_and_undo_relight_like_fresh_lighting() {
+        let block = |name| BlockState::new(BlockType::from_name(name).unwrap());
+        let mut world = World::new();
+        world.fill(Region::new(ICoords::new(0, 0, 0), ICoords::new(63, 20, 63)), block("stone")).unwrap();
+        world.fill(Region::new(ICoords::new(0, 63, 0), ICoords::new(63, 63, 63)), block("glass")).unwrap();
+        assert_lit_like_fresh(&world);
+
+        // a roof across the segment boundaries shades the ground, a light below it brightens it again
+        world.fill(Region::new(ICoords::new(10, 40, 10), ICoords::new(50, 41, 50)), block("stone")).unwrap();
+        assert_lit_like_fresh(&world);
+        world.fill(Region::new(ICoords::new(30, 21, 30), ICoords::new(33, 22, 33)), block("glowstone")).unwrap();
+        assert_lit_like_fresh(&world);
+        world.replace(Region::new(ICoords::new(0, 0, 0), ICoords::new(63, 63, 63)), block("stone").block, block("glass")).unwrap();
+        assert_lit_like_fresh(&world);
+
+        for _ in 0..3 {
+            world.undo().unwrap();
+            assert_lit_like_fresh(&world);
+        }
+        world.redo().unwrap();
+        assert_lit_like_fresh(&world);
+    }
+}
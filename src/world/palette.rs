@@ -0,0 +1,227 @@
+/// a fixed length array of `T` that stores each element as a bit-packed index into a palette of distinct values
+///
+/// - the palette grows automatically (doubling the bits per index) when a new value does not fit anymore
+/// - palette entries that are no longer referenced are reused before the palette grows
+/// - an array that only contains a single value is stored without any index data
+#[derive(Clone, Debug)]
+pub struct PalettedVec<T> {
+    len: usize,
+    palette: Vec<T>,
+    // number of elements referencing each palette entry
+    counts: Vec<usize>,
+    // bits per index, always a power of two so that indices never straddle two words
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl<T: Copy + Eq> PalettedVec<T> {
+    /// an array of `len` copies of `value`
+    pub fn new(len: usize, value: T) -> Self {
+        PalettedVec {
+            len,
+            palette: vec![value],
+            counts: vec![len],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// returns `Some(value)` if all elements are equal to `value`
+    pub fn uniform(&self) -> Option<T> {
+        if self.bits == 0 {
+            return Some(self.palette[0]);
+        }
+        None
+    }
+
+    /// the values that are currently referenced by at least one element, together with their number of occurrences
+    pub fn palette(&self) -> impl Iterator<Item = (T, usize)> + '_ {
+        self.palette.iter().zip(&self.counts).filter(|(_, &c)| c > 0).map(|(&v, &c)| (v, c))
+    }
+
+    pub fn get(&self, i: usize) -> T {
+        self.palette[self.index(i)]
+    }
+
+    /// sets element `i` to `value` and returns the previous value
+    pub fn set(&mut self, i: usize, value: T) -> T {
+        let old_index = self.index(i);
+        let old = self.palette[old_index];
+        if old == value {
+            return old;
+        }
+
+        let new_index = self.palette_index(value);
+        self.counts[old_index] -= 1;
+        self.counts[new_index] += 1;
+        self.write_index(i, new_index);
+
+        if self.counts[new_index] == self.len {
+            self.fill(value);
+        }
+        old
+    }
+
+    /// sets all elements to `value`
+    pub fn fill(&mut self, value: T) {
+        self.palette = vec![value];
+        self.counts = vec![self.len];
+        self.bits = 0;
+        self.data = Vec::new();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    /// approximate number of bytes used on the heap
+    pub fn memory_usage(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<T>()
+            + self.counts.capacity() * std::mem::size_of::<usize>()
+            + self.data.capacity() * std::mem::size_of::<u64>()
+    }
+
+    fn index(&self, i: usize) -> usize {
+        assert!(i < self.len, "index {} out of bounds for length {}", i, self.len);
+        if self.bits == 0 {
+            return 0;
+        }
+        read_packed(&self.data, self.bits, i)
+    }
+
+    fn write_index(&mut self, i: usize, index: usize) {
+        write_packed(&mut self.data, self.bits, i, index);
+    }
+
+    /// finds or allocates the palette entry for `value`
+    fn palette_index(&mut self, value: T) -> usize {
+        if let Some(index) = self.palette.iter().position(|&v| v == value) {
+            return index;
+        }
+        // reuse an entry that is no longer referenced
+        if let Some(index) = self.counts.iter().position(|&c| c == 0) {
+            self.palette[index] = value;
+            return index;
+        }
+
+        if self.palette.len() >= 1 << self.bits {
+            self.grow();
+        }
+        self.palette.push(value);
+        self.counts.push(0);
+        self.palette.len() - 1
+    }
+
+    /// doubles the number of bits per index and repacks the data
+    fn grow(&mut self) {
+        let old_bits = self.bits;
+        let old_data = std::mem::take(&mut self.data);
+        self.bits = if old_bits == 0 { 1 } else { 2 * old_bits };
+        self.data = vec![0; self.len.div_ceil((u64::BITS / self.bits) as usize)];
+        if old_bits == 0 {
+            // all elements referenced palette entry 0
+            return;
+        }
+        for i in 0..self.len {
+            write_packed(&mut self.data, self.bits, i, read_packed(&old_data, old_bits, i));
+        }
+    }
+}
+
+pub(super) fn read_packed(data: &[u64], bits: u32, i: usize) -> usize {
+    let per_word = (u64::BITS / bits) as usize;
+    let shift = (i % per_word) as u32 * bits;
+    ((data[i / per_word] >> shift) & ((1u64 << bits) - 1)) as usize
+}
+
+pub(super) fn write_packed(data: &mut [u64], bits: u32, i: usize, index: usize) {
+    let per_word = (u64::BITS / bits) as usize;
+    let shift = (i % per_word) as u32 * bits;
+    let mask = ((1u64 << bits) - 1) << shift;
+    data[i / per_word] = (data[i / per_word] & !mask) | ((index as u64) << shift);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_grow_with_the_palette() {
+        let len = 1000;
+        let mut v = PalettedVec::new(len, 0u32);
+        assert_eq!(v.bits, 0);
+        // the number of distinct values after which each bit width is needed
+        for (n_values, bits) in [(2, 1), (3, 2), (5, 4), (17, 8), (257, 16)] {
+            for value in 0..n_values {
+                v.set(value as usize, value);
+            }
+            assert_eq!(v.bits, bits, "{} values", n_values);
+            assert_eq!(v.palette().count(), n_values as usize);
+        }
+        // growing keeps all values
+        for i in 0..len {
+            assert_eq!(v.get(i), if i < 257 { i as u32 } else { 0 }, "{}", i);
+        }
+        assert_eq!(v.palette().map(|(_, n)| n).sum::<usize>(), len);
+    }
+
+    #[test]
+    fn unused_entries_are_reused() {
+        let mut v = PalettedVec::new(64, 'a');
+        v.set(0, 'b');
+        v.set(1, 'c');
+        assert_eq!(v.bits, 2);
+        v.set(0, 'a');
+        v.set(1, 'd');
+        assert_eq!(v.bits, 2);
+        assert_eq!(v.palette().collect::<Vec<_>>(), [('a', 63), ('d', 1)]);
+    }
+
+    #[test]
+    fn packed_indices_at_word_boundaries() {
+        for bits in [1, 2, 4, 8, 16, 32] {
+            let per_word = (u64::BITS / bits) as usize;
+            let max = ((1u64 << bits) - 1) as usize;
+            let mut data = vec![0u64; 3];
+            let mut expected = vec![0; 3 * per_word];
+            for (i, index) in [(0, max), (per_word - 1, max), (per_word, max), (2 * per_word - 1, max), (2 * per_word, max / 2), (per_word - 1, 1)] {
+                write_packed(&mut data, bits, i, index);
+                expected[i] = index;
+            }
+            for (i, &index) in expected.iter().enumerate() {
+                assert_eq!(read_packed(&data, bits, i), index, "{} bits, index {}", bits, i);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_arrays_have_no_index_data() {
+        let mut v = PalettedVec::new(100, 1u8);
+        assert_eq!(v.uniform(), Some(1));
+        for i in 0..100 {
+            v.set(i, (i % 3) as u8);
+        }
+        assert_eq!(v.uniform(), None);
+        // setting the last differing element collapses the palette
+        for i in 0..100 {
+            v.set(i, 7);
+        }
+        assert_eq!(v.uniform(), Some(7));
+        assert_eq!((v.bits, v.data.len()), (0, 0));
+        assert_eq!(v.palette().collect::<Vec<_>>(), [(7, 100)]);
+
+        v.set(5, 1);
+        v.fill(2);
+        assert_eq!(v.uniform(), Some(2));
+        assert_eq!(v.palette().collect::<Vec<_>>(), [(2, 100)]);
+        assert!(v.iter().all(|x| x == 2));
+    }
+}
@@ -0,0 +1,393 @@
+// on-disk format of a world
+//
+// a saved world is a directory containing
+// - `world.dat`: the world header (magic, format version, seed) followed by the block table
+// - one region file `r.<x>.<y>.<z>.cl3` per L3 segment, where x, y, z are the global L3 coordinates
+//
+// region file layout (all integers little endian):
+// ```text
+// magic "CTRR" | version u32 | x i64 | y i64 | z i64
+// L2 presence bitmap (L3_SIZE.volume() bits)
+// for every present L2 segment:
+//     L1 presence bitmap (L2_SIZE.volume() bits)
+//     for every present L1 segment:
+//         number of runs u32
+//         runs: (length u16, block id u16, state data u16)
+// ```
+// segments that are `None` only cost a zero bit in the presence bitmap of their parent.
+//
+// block table layout:
+// ```text
+// number of entries u32
+// entries: (block id u16, name length u16, name utf-8)
+// ```
+// block ids in region files are mapped to block types by name on load, so that a world stays valid
+// when the ids in the block definitions change.
+
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use super::*;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const WORLD_MAGIC: &[u8; 4] = b"CTRW";
+const REGION_MAGIC: &[u8; 4] = b"CTRR";
+const WORLD_FILE: &str = "world.dat";
+const REGION_EXTENSION: &str = "cl3";
+
+pub struct WorldHeader {
+    pub seed: u32,
+}
+
+pub(super) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(super) fn write_u16(w: &mut impl Write, v: u16) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(super) fn write_u32(w: &mut impl Write, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_i64(w: &mut impl Write, v: i64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(super) fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+pub(super) fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i64(r: &mut impl Read) -> io::Result<i64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+fn read_header(r: &mut impl Read, magic: &[u8; 4]) -> io::Result<()> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    if &buf != magic {
+        return Err(invalid_data(format!("bad magic {:?}, expected {:?}", buf, magic)));
+    }
+    let version = read_u32(r)?;
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(format!("unsupported format version {}", version)));
+    }
+    Ok(())
+}
+
+/// one bit per entry of `segments`, set if the entry is `Some`
+fn write_presence<T>(w: &mut impl Write, segments: &[Option<T>]) -> io::Result<()> {
+    for chunk in segments.chunks(8) {
+        let byte = chunk.iter().enumerate().fold(0u8, |acc, (i, s)| acc | ((s.is_some() as u8) << i));
+        w.write_all(&[byte])?;
+    }
+    Ok(())
+}
+
+fn read_presence(r: &mut impl Read, n: usize) -> io::Result<Vec<bool>> {
+    let mut bytes = vec![0u8; n.div_ceil(8)];
+    r.read_exact(&mut bytes)?;
+    Ok((0..n).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect())
+}
+
+/// run length encoding of the block states of `seg`
+fn write_l1_segment(w: &mut impl Write, seg: &L1Segment) -> io::Result<()> {
+    let mut runs: Vec<(u16, BlockState)> = Vec::new();
+    for block in seg.blocks() {
+        match runs.last_mut() {
+            Some((length, state)) if *state == block && *length < u16::MAX => *length += 1,
+            _ => runs.push((1, block)),
+        }
+    }
+
+    write_u32(w, runs.len() as u32)?;
+    for (length, state) in runs {
+        write_u16(w, length)?;
+        write_u16(w, state.block.id())?;
+        write_u16(w, state.data())?;
+    }
+    Ok(())
+}
+
+/// the block type that each id stored in a save refers to
+type BlockTable = HashMap<u16, BlockType>;
+
+fn lookup_block(table: &BlockTable, id: u16) -> io::Result<BlockType> {
+    table.get(&id).copied().ok_or_else(|| invalid_data(format!("block id {} is not in the block table", id)))
+}
+
+/// writes the name of every registered block type, so that ids can be mapped back on load
+fn write_block_table(w: &mut impl Write) -> io::Result<()> {
+    let blocks: Vec<BlockType> = registry::registry().block_types().collect();
+    write_u32(w, blocks.len() as u32)?;
+    for block in blocks {
+        let name = block.name().as_bytes();
+        write_u16(w, block.id())?;
+        write_u16(w, name.len() as u16)?;
+        w.write_all(name)?;
+    }
+    Ok(())
+}
+
+fn read_block_table(r: &mut impl Read) -> io::Result<BlockTable> {
+    let n = read_u32(r)?;
+    let mut table = HashMap::new();
+    for _ in 0..n {
+        let id = read_u16(r)?;
+        let mut name = vec![0u8; read_u16(r)? as usize];
+        r.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|e| invalid_data(e.to_string()))?;
+        let block = BlockType::from_name(&name).ok_or_else(|| invalid_data(format!("unknown block type `{}`", name)))?;
+        table.insert(id, block);
+    }
+    Ok(table)
+}
+
+fn read_l1_segment(r: &mut impl Read, table: &BlockTable) -> io::Result<L1Segment> {
+    let n_runs = read_u32(r)?;
+    let mut seg = L1Segment::default();
+    let mut i = 0;
+    for _ in 0..n_runs {
+        let length = read_u16(r)? as usize;
+        let id = read_u16(r)?;
+        let data = read_u16(r)?;
+        let block = BlockState::from_raw(lookup_block(table, id)?, data);
+        if i + length > L1_SIZE_BL.volume() as usize {
+            return Err(invalid_data(format!("L1 segment has more than {} blocks", L1_SIZE_BL.volume())));
+        }
+        if n_runs == 1 {
+            seg.fill(block);
+        } else if block != BlockState::NO_BLOCK {
+            for j in i..i + length {
+                seg.set_index(j, block);
+            }
+        }
+        i += length;
+    }
+
+    if i != L1_SIZE_BL.volume() as usize {
+        return Err(invalid_data(format!("L1 segment has {} blocks, expected {}", i, L1_SIZE_BL.volume())));
+    }
+    Ok(seg)
+}
+
+pub fn write_l3_segment(w: &mut impl Write, coords: ICoords, seg: &L3Segment) -> io::Result<()> {
+    w.write_all(REGION_MAGIC)?;
+    write_u32(w, FORMAT_VERSION)?;
+    write_i64(w, coords.x)?;
+    write_i64(w, coords.y)?;
+    write_i64(w, coords.z)?;
+
+    write_presence(w, &seg.sub_segments)?;
+    for l2 in seg.sub_segments.iter().flatten() {
+        write_presence(w, &l2.sub_segments)?;
+        for l1 in l2.sub_segments.iter().flatten() {
+            write_l1_segment(w, l1)?;
+        }
+    }
+    Ok(())
+}
+
+/// returns the global coordinates of the L3 segment and the segment itself.
+/// block ids are translated through `table`.
+pub fn read_l3_segment(r: &mut impl Read, table: &BlockTable) -> io::Result<(ICoords, L3Segment)> {
+    read_header(r, REGION_MAGIC)?;
+    let coords = ICoords::new(read_i64(r)?, read_i64(r)?, read_i64(r)?);
+
+    let mut l3 = L3Segment::default();
+    let l2_present = read_presence(r, l3.sub_segments.len())?;
+    for (i, present) in l2_present.into_iter().enumerate() {
+        if !present {
+            continue;
+        }
+        let mut l2 = L2Segment::default();
+        let l1_present = read_presence(r, l2.sub_segments.len())?;
+        for (j, present) in l1_present.into_iter().enumerate() {
+            if present {
+                l2.sub_segments[j] = Some(read_l1_segment(r, table)?);
+            }
+        }
+        l3.sub_segments[i] = Some(l2);
+    }
+    Ok((coords, l3))
+}
+
+/// writes a file through `write` into a temporary file next to `path` and renames it into place once it is complete,
+/// so that an error or a crash while writing leaves the previous file intact
+fn write_file_atomically(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = File::create(&tmp_path).and_then(|file| {
+        let mut w = BufWriter::new(file);
+        write(&mut w)?;
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }).and_then(|()| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+pub fn save_world(path: &Path, header: &WorldHeader, terrain: &HashMap<ICoords, L3Segment>) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+
+    write_file_atomically(&path.join(WORLD_FILE), |w| {
+        w.write_all(WORLD_MAGIC)?;
+        write_u32(w, FORMAT_VERSION)?;
+        write_u32(w, header.seed)?;
+        write_block_table(w)
+    })?;
+
+    let mut written = HashSet::new();
+    for (coords, l3) in terrain {
+        let file_name = format!("r.{}.{}.{}.{}", coords.x, coords.y, coords.z, REGION_EXTENSION);
+        write_file_atomically(&path.join(&file_name), |w| write_l3_segment(w, *coords, l3))?;
+        written.insert(OsString::from(file_name));
+    }
+
+    // remove regions of a previous save that no longer exist in `terrain`, only once everything else has been written
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        if entry_path.extension() == Some(OsStr::new(REGION_EXTENSION)) && !written.contains(&entry.file_name()) {
+            fs::remove_file(entry_path)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn load_world(path: &Path) -> io::Result<(WorldHeader, HashMap<ICoords, L3Segment>)> {
+    let mut r = BufReader::new(File::open(path.join(WORLD_FILE))?);
+    read_header(&mut r, WORLD_MAGIC)?;
+    let header = WorldHeader { seed: read_u32(&mut r)? };
+    let table = read_block_table(&mut r)?;
+
+    let mut terrain = HashMap::new();
+    for entry in fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if entry_path.extension() != Some(OsStr::new(REGION_EXTENSION)) {
+            continue;
+        }
+        let mut r = BufReader::new(File::open(&entry_path)?);
+        let (coords, l3) = read_l3_segment(&mut r, &table)?;
+        terrain.insert(coords, l3);
+    }
+    Ok((header, terrain))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    /// an empty directory for the test called `name`
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("citrus-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// states with non-default properties
+    fn states() -> [BlockState; 3] {
+        [
+            BlockState::new(BlockType::from_name("furnace").unwrap()).with_facing(Face::ZNeg),
+            BlockState::new(BlockType::from_name("log").unwrap()).with_axis(BlockAxis::X),
+            BlockState::new(BlockType::from_name("wool").unwrap()).with_variant(5),
+        ]
+    }
+
+    /// an l3 segment with an empty l2 segment and an l2 segment with a mixed, an empty and a full l1 segment
+    fn sparse_l3() -> L3Segment {
+        let grass = BlockType::from_name("grass").unwrap();
+        let mut mixed = L1Segment::default();
+        for i in (0..L1_SIZE_BL.volume() as usize).step_by(7) {
+            mixed.set_index(i, grass.into());
+        }
+        for (i, state) in states().into_iter().enumerate() {
+            mixed.set_index(1 + i, state);
+        }
+        let mut full = L1Segment::default();
+        full.fill(grass.into());
+
+        let mut l2 = L2Segment::default();
+        l2.sub_segments[0] = Some(mixed);
+        l2.sub_segments[9] = Some(L1Segment::default());
+        l2.sub_segments[L2_SIZE.volume() as usize - 1] = Some(full);
+        let mut l3 = L3Segment::default();
+        l3.sub_segments[3] = Some(l2);
+        l3.sub_segments[L3_SIZE.volume() as usize - 1] = Some(L2Segment::default());
+        l3
+    }
+
+    fn encode(coords: ICoords, l3: &L3Segment) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_l3_segment(&mut bytes, coords, l3).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip_of_sparse_segments() {
+        let grass = BlockType::from_name("grass").unwrap();
+        let dir = test_dir("round-trip");
+        let terrain = HashMap::from([(ICoords::new(0, 0, 0), sparse_l3()), (ICoords::new(-1, 2, -3), L3Segment::default())]);
+        save_world(&dir, &WorldHeader { seed: 42 }, &terrain).unwrap();
+        let (header, loaded) = load_world(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(header.seed, 42);
+        assert_eq!(loaded.len(), 2);
+        for (coords, l3) in &terrain {
+            assert_eq!(encode(*coords, &loaded[coords]), encode(*coords, l3), "{:?}", coords);
+        }
+        let l3 = &loaded[&ICoords::new(0, 0, 0)];
+        let present: Vec<usize> = (0..l3.sub_segments.len()).filter(|&i| l3.sub_segments[i].is_some()).collect();
+        assert_eq!(present, [3, L3_SIZE.volume() as usize - 1]);
+        let l2 = l3.sub_segments[3].as_ref().unwrap();
+        assert_eq!(l2.number_of_l1_segments(), 3);
+        assert!(l2.sub_segments[1].is_none());
+        let mixed = l2.sub_segments[0].as_ref().unwrap();
+        assert_eq!(mixed.get_index(7), BlockState::from(grass));
+        assert_eq!(mixed.get_index(8), BlockState::NO_BLOCK);
+        for (i, state) in states().into_iter().enumerate() {
+            assert_eq!(mixed.get_index(1 + i), state);
+        }
+        assert_eq!(mixed.get_index(1).facing(), Face::ZNeg);
+        assert_eq!(mixed.get_index(3).variant(), 5);
+    }
+
+    #[test]
+    fn saving_removes_stale_regions_only_after_writing() {
+        let dir = test_dir("stale-regions");
+        let a = ICoords::new(0, 0, 0);
+        let b = ICoords::new(1, 0, 0);
+        save_world(&dir, &WorldHeader { seed: 1 }, &HashMap::from([(a, sparse_l3()), (b, sparse_l3())])).unwrap();
+        save_world(&dir, &WorldHeader { seed: 1 }, &HashMap::from([(a, L3Segment::default())])).unwrap();
+        let (_, loaded) = load_world(&dir).unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), [&a]);
+
+        // a region that cannot be written leaves the previous save intact
+        let blocked = dir.join(format!("r.0.0.0.{}.tmp", REGION_EXTENSION));
+        fs::create_dir(&blocked).unwrap();
+        assert!(save_world(&dir, &WorldHeader { seed: 1 }, &HashMap::from([(a, sparse_l3())])).is_err());
+        fs::remove_dir(&blocked).unwrap();
+        let (_, loaded) = load_world(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(encode(a, &loaded[&a]), encode(a, &L3Segment::default()));
+    }
+}
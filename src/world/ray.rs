@@ -6,43 +6,79 @@ pub struct Ray {
     pub direction: Vec3
 }
 
-trait AbsMin {
-    fn abs_min(self, b: Self) -> Self;
-}
-
-impl AbsMin for f32 {
-    fn abs_min(self, b: f32) -> f32 {
-        if self.abs() < b.abs() {
-            return self;
-        }
-        return b;
-    }
-}
-
 impl Ray {
-    fn round_in_dir(x: f32, direction: f32) -> f32 {
-        if x == x.round() {
-            // x represents an integer
-            return x + direction.signum();
-        }
+    // distance along the ray (in multiples of `direction`) to the first block boundary on one axis, and the step direction
+    fn first_boundary(origin: f32, direction: f32) -> (f32, i64) {
         if direction > 0. {
-            return x.ceil();
+            return ((origin.floor() + 1. - origin) / direction, 1);
+        }
+        if direction < 0. {
+            return ((origin - origin.floor()) / -direction, -1);
         }
-        return x.floor();
+        (f32::INFINITY, 0)
     }
 
-    // return the first intersected blocks within n units
+    // return the first intersected blocks within n units, in the order in which the ray traverses them
     pub fn intersected_blocks(&self, n: usize) -> Vec<ICoords> {
         let mut coords = Vec::with_capacity(n);
-        let mut p = self.origin;
-        while self.origin.distance_squared(p) < (n * n) as f32 {
-            coords.push(ICoords {x: p.x as i64, y: p.y as i64, z: p.z as i64});
-            let delta_x = Self::round_in_dir(p.x, self.direction.x) - p.x;
-            let delta_y = Self::round_in_dir(p.y, self.direction.y) - p.y;
-            let delta_z = Self::round_in_dir(p.z, self.direction.z) - p.z;
-            let r = (delta_x / self.direction.x).abs_min(delta_y / self.direction.y).abs_min(delta_z / self.direction.z);
-            p += r * self.direction;
+        // blocks are addressed by their minimum corner, so flooring works for negative coordinates as well
+        let mut block = ICoords::from_vec3(self.origin);
+        let (mut t_x, step_x) = Self::first_boundary(self.origin.x, self.direction.x);
+        let (mut t_y, step_y) = Self::first_boundary(self.origin.y, self.direction.y);
+        let (mut t_z, step_z) = Self::first_boundary(self.origin.z, self.direction.z);
+        let delta = self.direction.abs().recip();
+        let t_end = n as f32 / self.direction.length();
+
+        loop {
+            coords.push(block);
+            if t_x <= t_y && t_x <= t_z {
+                if t_x >= t_end {
+                    break;
+                }
+                block.x += step_x;
+                t_x += delta.x;
+            } else if t_y <= t_z {
+                if t_y >= t_end {
+                    break;
+                }
+                block.y += step_y;
+                t_y += delta.y;
+            } else {
+                if t_z >= t_end {
+                    break;
+                }
+                block.z += step_z;
+                t_z += delta.z;
+            }
+        }
+        coords
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crosses_zero_along_an_axis() {
+        let ray = Ray { origin: Vec3::new(-2.5, 0.5, -0.5), direction: Vec3::X };
+        let xs: Vec<i64> = ray.intersected_blocks(4).iter().map(|c| c.x).collect();
+        assert_eq!(xs, [-3, -2, -1, 0, 1]);
+        assert!(ray.intersected_blocks(4).iter().all(|c| c.y == 0 && c.z == -1));
+    }
+
+    #[test]
+    fn crosses_zero_diagonally() {
+        let ray = Ray { origin: Vec3::new(-1.25, -1.5, 0.75), direction: Vec3::new(1., 1., -1.).normalize() };
+        let blocks = ray.intersected_blocks(4);
+        assert_eq!(blocks[0], ICoords::new(-2, -2, 0));
+        // every step moves to a face neighbour
+        for pair in blocks.windows(2) {
+            let [a, b] = [pair[0], pair[1]];
+            assert_eq!((b.x - a.x).abs() + (b.y - a.y).abs() + (b.z - a.z).abs(), 1, "{:?} -> {:?}", a, b);
         }
-        return coords;
+        assert!(blocks.contains(&ICoords::new(0, 0, -1)));
+        let last = *blocks.last().unwrap();
+        assert!(last.x >= 0 && last.y >= 0 && last.z < 0, "{:?}", last);
     }
 }
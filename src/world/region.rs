@@ -0,0 +1,424 @@
+use super::*;
+use super::palette::PalettedVec;
+use super::size::Size3D;
+
+/// an axis aligned box of blocks, `min` and `max` are inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub min: ICoords,
+    pub max: ICoords,
+}
+
+impl Region {
+    /// the smallest region containing both `a` and `b`
+    pub fn new(a: ICoords, b: ICoords) -> Self {
+        Region {
+            min: ICoords::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: ICoords::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// the region of size `size` whose minimum corner is at `origin`
+    pub fn with_size(origin: ICoords, size: Size3D) -> Self {
+        Region { min: origin, max: origin + size.into() - ICoords::new(1, 1, 1) }
+    }
+
+    /// the number of blocks in the region, `None` if it does not fit into a u64
+    pub fn volume(&self) -> Option<u64> {
+        let d = [self.max.x.checked_sub(self.min.x)?, self.max.y.checked_sub(self.min.y)?, self.max.z.checked_sub(self.min.z)?];
+        d.into_iter().try_fold(1u64, |volume, d| volume.checked_mul(d as u64 + 1))
+    }
+
+    pub fn size(&self) -> Size3D {
+        let d = self.max - self.min;
+        Size3D { x: d.x as u64 + 1, y: d.y as u64 + 1, z: d.z as u64 + 1 }
+    }
+
+    pub fn contains(&self, c: ICoords) -> bool {
+        (self.min.x..=self.max.x).contains(&c.x)
+            && (self.min.y..=self.max.y).contains(&c.y)
+            && (self.min.z..=self.max.z).contains(&c.z)
+    }
+
+    pub fn in_range(&self) -> bool {
+        self.min.in_range() && self.max.in_range()
+    }
+
+    /// whether a single edit may change the region: it has to be in range and at most `config::MAX_EDIT_VOLUME` blocks large
+    pub fn check_editable(&self) -> Result<(), WorldError> {
+        if !self.in_range() {
+            return Err(WorldError::OutOfRange(if self.min.in_range() { self.max } else { self.min }));
+        }
+        match self.volume() {
+            Some(volume) if volume <= config::MAX_EDIT_VOLUME => Ok(()),
+            volume => Err(WorldError::TooLarge(volume)),
+        }
+    }
+
+    /// the region of global segment coordinates of the l1 segments that the region intersects
+    pub fn l1_region(&self) -> Region {
+        Region { min: self.min.l1_glob(), max: self.max.l1_glob() }
+    }
+
+    /// the part of the region inside every l1 segment it intersects, as
+    /// (global coordinates of the segment, local min, local max)
+    fn l1_parts(&self) -> Vec<(ICoords, ICoords, ICoords)> {
+        let size: ICoords = L1_SIZE_BL.into();
+        let (first, last) = (self.min.l1_glob(), self.max.l1_glob());
+        let mut parts = Vec::new();
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                for z in first.z..=last.z {
+                    let l1_glob = ICoords::new(x, y, z);
+                    let origin = l1_glob * size;
+                    let clamp = |c: ICoords| ICoords::new(
+                        (c.x - origin.x).clamp(0, size.x - 1),
+                        (c.y - origin.y).clamp(0, size.y - 1),
+                        (c.z - origin.z).clamp(0, size.z - 1));
+                    parts.push((l1_glob, clamp(self.min), clamp(self.max)));
+                }
+            }
+        }
+        parts
+    }
+}
+
+// an edit of a cube of `config::MAX_EDIT_VOLUME` blocks (256³) intersects at most 9³ l1 segments. their images in the undo history
+// take at most 64 KiB each with 16 bits per block, so that such an edit fits into `config::HISTORY_MEMORY_LIMIT`.
+// the images of other edits are checked against the limit before anything is changed, see `World::reserve_images`.
+const _: () = assert!(config::MAX_EDIT_VOLUME == 256 * 256 * 256 && 9 * 9 * 9 * 64 * 1024 <= config::HISTORY_MEMORY_LIMIT);
+
+/// whether the local box `min`..=`max` covers a whole l1 segment
+fn covers_segment(min: ICoords, max: ICoords) -> bool {
+    min == ICoords::new(0, 0, 0) && max + ICoords::new(1, 1, 1) == L1_SIZE_BL.into()
+}
+
+/// local coordinates of all blocks in the box `min`..=`max`
+fn box_coords(min: ICoords, max: ICoords) -> impl Iterator<Item = ICoords> {
+    let size = Region::new(min, max).size();
+    size.into_iter().map(move |d| min + d)
+}
+
+/// a copied box of blocks, positioned relative to its minimum corner
+#[derive(Clone, Debug)]
+pub struct Clipboard {
+    size: Size3D,
+    blocks: PalettedVec<BlockState>,
+}
+
+impl Clipboard {
+    /// a clipboard of `size` filled with air
+    pub fn new(size: Size3D) -> Self {
+        Clipboard { size, blocks: PalettedVec::new(size.volume() as usize, BlockState::NO_BLOCK) }
+    }
+
+    pub fn size(&self) -> Size3D {
+        self.size
+    }
+
+    /// - `coords`: position relative to the minimum corner
+    pub fn get(&self, coords: ICoords) -> BlockState {
+        self.blocks.get(self.size.c1d(coords) as usize)
+    }
+
+    pub fn set(&mut self, coords: ICoords, block: BlockState) {
+        self.blocks.set(self.size.c1d(coords) as usize, block);
+    }
+
+    pub fn fill(&mut self, block: BlockState) {
+        self.blocks.fill(block);
+    }
+
+    /// the clipboard rotated by `turns` quarter turns around the y axis, from x towards z.
+    /// the rotated blocks are again positioned relative to the minimum corner.
+    pub fn rotated_y(&self, turns: u32) -> Self {
+        let mut rotated = self.clone();
+        for _ in 0..turns % 4 {
+            let src = rotated;
+            rotated = Clipboard::new(Size3D { x: src.size.z, y: src.size.y, z: src.size.x });
+            for c in src.size {
+                let target = ICoords::new(src.size.z as i64 - 1 - c.z, c.y, c.x);
+                rotated.set(target, src.get(c).rotated_y(1));
+            }
+        }
+        rotated
+    }
+
+    /// the clipboard mirrored along `axis`
+    pub fn mirrored(&self, axis: Axis) -> Self {
+        let mut mirrored = Clipboard::new(self.size);
+        for c in self.size {
+            let target = match axis {
+                Axis::X => ICoords::new(self.size.x as i64 - 1 - c.x, c.y, c.z),
+                Axis::Y => ICoords::new(c.x, self.size.y as i64 - 1 - c.y, c.z),
+                Axis::Z => ICoords::new(c.x, c.y, self.size.z as i64 - 1 - c.z),
+            };
+            mirrored.set(target, self.get(c).mirrored(axis));
+        }
+        mirrored
+    }
+}
+
+impl World {
+    /// global coordinates of the loaded l1 segments that intersect `region`, ordered by x, y and z.
+    /// only the loaded segments are visited, so `region` may be arbitrarily large.
+    pub fn loaded_l1_segments_in(&self, region: Region) -> Vec<ICoords> {
+        let l1_region = region.l1_region();
+        let mut segments: Vec<ICoords> = self.l1_segment_coords().into_iter().filter(|&c| l1_region.contains(c)).collect();
+        segments.sort_by_key(|c| (c.x, c.y, c.z));
+        segments
+    }
+
+    /// runs `edit` inside an operation called `name`, unless an operation is already open
+    fn edit_operation<T>(&mut self, name: &str, edit: impl FnOnce(&mut Self) -> Result<T, WorldError>) -> Result<T, WorldError> {
+        let open = self.history.is_recording();
+        if !open {
+            self.history.begin(name);
+        }
+        let result = edit(self);
+        if !open {
+            self.history.end();
+        }
+        self.update_light();
+        result
+    }
+
+    /// reserves undo history for an image of every l1 segment in `parts` that `changes` is true for,
+    /// so that an edit that does not fit into the history is refused before anything has been changed
+    fn reserve_images(&mut self, parts: &[(ICoords, ICoords, ICoords)], changes: impl Fn(Option<&L1Segment>) -> bool) -> Result<(), WorldError> {
+        let empty = history::image_size(&L1Segment::default());
+        let mut bytes = 0usize;
+        for &(l1_glob, _, _) in parts {
+            let seg = self.l1_segment(l1_glob * L1_SIZE_BL.into());
+            if changes(seg) {
+                bytes = bytes.saturating_add(seg.map_or(empty, history::image_size));
+            }
+        }
+        self.history.reserve(bytes)
+    }
+
+    /// records the blocks of the l1 segment at `l1_glob` before `n_changed` of them were changed within the local box `min`..=`max`.
+    /// the segment is relit as a whole by `update_light`, which is cheaper than relighting every changed block.
+    fn commit_changes(&mut self, l1_glob: ICoords, min: ICoords, max: ICoords, before: PalettedVec<BlockState>, n_changed: usize) {
+        if n_changed == 0 {
+            return;
+        }
+        self.history.record_image(l1_glob, before);
+        self.light_segments.insert(l1_glob);
+
+        // neighbours only need to be remeshed if the box touches their face
+        self.dirty.insert(l1_glob);
+        for face in Face::all() {
+            if !L1_SIZE_BL.contains(min + face.numeric()) || !L1_SIZE_BL.contains(max + face.numeric()) {
+                self.dirty.insert(l1_glob + face.numeric());
+            }
+        }
+    }
+
+    /// sets all blocks in `region` to `block` and returns the number of changed blocks
+    pub fn fill(&mut self, region: Region, block: impl Into<BlockState>) -> Result<usize, WorldError> {
+        region.check_editable()?;
+        let block = block.into().normalized();
+        self.edit_operation("fill", |world| {
+            let parts = region.l1_parts();
+            world.reserve_images(&parts, |seg| seg.and_then(L1Segment::uniform) != Some(block))?;
+            let mut n_changed = 0;
+            for (l1_glob, min, max) in parts {
+                let seg = world.create_or_get_l1(l1_glob * L1_SIZE_BL.into());
+                if seg.uniform() == Some(block) {
+                    continue;
+                }
+
+                let before = seg.blocks_image();
+                let mut n = 0;
+                if covers_segment(min, max) {
+                    n = seg.blocks().filter(|&b| b != block).count();
+                    seg.fill(block);
+                } else {
+                    for local in box_coords(min, max) {
+                        if seg.set(local, block) != block {
+                            n += 1;
+                        }
+                    }
+                }
+                n_changed += n;
+                world.commit_changes(l1_glob, min, max, before, n);
+            }
+            Ok(n_changed)
+        })
+    }
+
+    /// replaces all blocks of type `from` in `region` by `to` and returns the number of changed blocks.
+    /// unloaded segments are only created if `from` is air.
+    pub fn replace(&mut self, region: Region, from: BlockType, to: impl Into<BlockState>) -> Result<usize, WorldError> {
+        region.check_editable()?;
+        let to = to.into().normalized();
+        self.edit_operation("replace", |world| {
+            let parts = region.l1_parts();
+            world.reserve_images(&parts, |seg| match seg {
+                Some(seg) => seg.contains_block_type(from),
+                None => from == BlockType::NO_BLOCK,
+            })?;
+            let mut n_changed = 0;
+            for (l1_glob, min, max) in parts {
+                let coords = l1_glob * L1_SIZE_BL.into();
+                if from != BlockType::NO_BLOCK && world.l1_segment(coords).is_none() {
+                    continue;
+                }
+                let seg = world.create_or_get_l1(coords);
+                // most segments do not contain the block at all, which the palette tells without visiting any block
+                if !seg.contains_block_type(from) {
+                    continue;
+                }
+
+                let before = seg.blocks_image();
+                let mut n = 0;
+                if covers_segment(min, max) && seg.uniform().is_some() {
+                    if seg.uniform() != Some(to) {
+                        n = L1_SIZE_BL.volume() as usize;
+                        seg.fill(to);
+                    }
+                } else {
+                    for local in box_coords(min, max) {
+                        let block = seg.get(local);
+                        if block.block == from && block != to {
+                            seg.set(local, to);
+                            n += 1;
+                        }
+                    }
+                }
+                n_changed += n;
+                world.commit_changes(l1_glob, min, max, before, n);
+            }
+            Ok(n_changed)
+        })
+    }
+
+    /// copies the blocks in `region`. blocks of unloaded segments are copied as air.
+    pub fn copy(&self, region: Region) -> Result<Clipboard, WorldError> {
+        region.check_editable()?;
+        let mut clipboard = Clipboard::new(region.size());
+        for (l1_glob, min, max) in region.l1_parts() {
+            let origin = l1_glob * L1_SIZE_BL.into();
+            let Some(seg) = self.l1_segment(origin) else {
+                continue;
+            };
+            if seg.uniform() == Some(BlockState::NO_BLOCK) {
+                continue;
+            }
+            for local in box_coords(min, max) {
+                clipboard.set(origin + local - region.min, seg.get(local));
+            }
+        }
+        Ok(clipboard)
+    }
+
+    /// pastes `clipboard` with its minimum corner at `origin` and returns the number of changed blocks.
+    /// - `include_air`: whether air in the clipboard overwrites blocks in the world
+    pub fn paste(&mut self, clipboard: &Clipboard, origin: ICoords, include_air: bool) -> Result<usize, WorldError> {
+        let region = Region::with_size(origin, clipboard.size());
+        region.check_editable()?;
+        self.edit_operation("paste", |world| {
+            let parts = region.l1_parts();
+            world.reserve_images(&parts, |_| true)?;
+            let mut n_changed = 0;
+            for (l1_glob, min, max) in parts {
+                let seg_origin = l1_glob * L1_SIZE_BL.into();
+                let seg = world.create_or_get_l1(seg_origin);
+                let before = seg.blocks_image();
+                let mut n = 0;
+                for local in box_coords(min, max) {
+                    let block = clipboard.get(seg_origin + local - origin);
+                    if !include_air && block == BlockState::NO_BLOCK {
+                        continue;
+                    }
+                    if seg.set(local, block) != block {
+                        n += 1;
+                    }
+                }
+                n_changed += n;
+                world.commit_changes(l1_glob, min, max, before, n);
+            }
+            Ok(n_changed)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_of_huge_regions_are_rejected() {
+        let mut world = World::new();
+        let huge = Region::new(ICoords::new(0, 0, 0), ICoords::new(100_000_000, 100_000_000, 100_000_000));
+        assert_eq!(world.copy(huge).err(), Some(WorldError::OutOfRange(huge.max)));
+        let whole = Region::new(ICoords::new(-MAX_COORD, -MAX_COORD, -MAX_COORD), ICoords::new(MAX_COORD, MAX_COORD, MAX_COORD));
+        assert_eq!(whole.volume(), None);
+        assert_eq!(world.copy(whole).err(), Some(WorldError::TooLarge(None)));
+        let large = Region::with_size(ICoords::new(-1000, 0, 0), Size3D { x: 2048, y: 2048, z: 8 });
+        assert_eq!(world.fill(large, BlockState::NO_BLOCK), Err(WorldError::TooLarge(Some(2048 * 2048 * 8))));
+        assert_eq!(world.replace(large, BlockType::NO_BLOCK, BlockState::NO_BLOCK), Err(WorldError::TooLarge(Some(2048 * 2048 * 8))));
+        assert!(world.terrain.is_empty());
+
+        let small = Region::new(ICoords::new(-2, -2, -2), ICoords::new(1, 1, 1));
+        assert_eq!(world.copy(small).map(|c| c.size()).ok(), Some(Size3D { x: 4, y: 4, z: 4 }));
+    }
+
+    #[test]
+    fn only_loaded_segments_in_a_region_are_listed() {
+        let mut world = World::new();
+        let stone = BlockState::new(BlockType::from_name("stone").unwrap());
+        for c in [ICoords::new(-1, 0, 0), ICoords::new(31, 31, 31), ICoords::new(32, 0, 0), ICoords::new(0, 1000, 0)] {
+            world.set_block(c, stone).unwrap();
+        }
+        let region = Region::new(ICoords::new(-1, 0, 0), ICoords::new(32, 0, 0));
+        assert_eq!(world.loaded_l1_segments_in(region), [ICoords::new(-1, 0, 0), ICoords::new(0, 0, 0), ICoords::new(1, 0, 0)]);
+        let whole = Region::new(ICoords::new(-MAX_COORD, -MAX_COORD, -MAX_COORD), ICoords::new(MAX_COORD, MAX_COORD, MAX_COORD));
+        assert_eq!(world.loaded_l1_segments_in(whole).len(), 4);
+    }
+
+    #[test]
+    fn the_history_of_region_edits_is_bounded_by_segments() {
+        let mut world = World::new();
+        let stone = BlockState::new(BlockType::from_name("stone").unwrap());
+        let glass = BlockState::new(BlockType::from_name("glass").unwrap());
+        let region = Region::new(ICoords::new(-64, -64, -64), ICoords::new(63, 63, 63));
+        assert_eq!(world.fill(region, stone), Ok(128 * 128 * 128));
+        let inner = Region::new(ICoords::new(-60, -60, -60), ICoords::new(59, 59, 59));
+        assert_eq!(world.replace(inner, stone.block, glass), Ok(120 * 120 * 120));
+        // one image per segment and operation, most of them uniform
+        assert!(world.history().memory_usage() < 1024 * 1024, "{}", world.history().memory_usage());
+
+        assert_eq!(world.undo().as_deref(), Some("replace"));
+        assert_eq!(world.get_block(ICoords::new(0, 0, 0)), stone);
+        assert_eq!(world.undo().as_deref(), Some("fill"));
+        assert_eq!(world.copy(region).unwrap().blocks.uniform(), Some(BlockState::NO_BLOCK));
+        assert_eq!(world.redo().as_deref(), Some("fill"));
+        assert_eq!(world.get_block(ICoords::new(-64, 63, 0)), stone);
+        assert_eq!(world.get_block(ICoords::new(-65, 63, 0)), BlockState::NO_BLOCK);
+    }
+
+    #[test]
+    fn region_edits_beyond_the_history_limit_are_refused() {
+        let mut world = World::new();
+        let stone = BlockState::new(BlockType::from_name("stone").unwrap());
+        let glass = BlockState::new(BlockType::from_name("glass").unwrap());
+        let region = Region::new(ICoords::new(0, 0, 0), ICoords::new(63, 31, 31));
+        let mut clipboard = Clipboard::new(region.size());
+        for (i, c) in region.size().into_iter().enumerate() {
+            clipboard.set(c, if i % 3 == 0 { stone } else { glass });
+        }
+        world.paste(&clipboard, region.min, true).unwrap();
+
+        // room for the image of one of the two segments
+        world.history = History::new(12 * 1024);
+        assert!(matches!(world.fill(region, BlockState::NO_BLOCK), Err(WorldError::HistoryFull(_))));
+        assert_eq!(world.get_block(ICoords::new(0, 0, 0)), stone);
+        assert_eq!(world.history().n_undo(), 0);
+        let half = Region::new(ICoords::new(0, 0, 0), ICoords::new(31, 31, 31));
+        assert_eq!(world.fill(half, BlockState::NO_BLOCK), Ok(32 * 32 * 32));
+        assert_eq!(world.undo().as_deref(), Some("fill"));
+        assert_eq!(world.get_block(ICoords::new(1, 0, 0)), glass);
+    }
+}
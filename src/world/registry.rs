@@ -0,0 +1,351 @@
+// registry of all block types and their properties, loaded from a definition file.
+//
+// definition file format:
+// ```text
+// # comment
+// [grass]                   <- name of the block type
+// id = 1                    <- numeric id stored in segments, 0 is reserved for air
+// solid = true              <- occupies its voxel (default true)
+// transparent = false       <- neighbouring faces are visible through the block (default false)
+// color = 0.0 1.0 0.0 1.0   <- rgba (default white)
+// light_emission = 0        <- 0 to 15 (default 0)
+// hardness = 0.6            <- (default 1.0)
+// collision = true          <- entities collide with the block (default: same as solid)
+// states = axis, half       <- state properties of the block, see `StateProperty` (default none)
+// texture = stone           <- texture of all faces, a png in `config::BLOCK_TEXTURES_PATH` (default: filled with the color)
+// texture_top = grass_top   <- texture of the top face, overrides `texture` (likewise texture_side and texture_bottom)
+// shape = slab              <- full, slab, stairs, cross or boxes, see `BlockShape::parse` (default full)
+// ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+use super::Face;
+use super::block::BlockType;
+use super::shape::BlockShape;
+use super::state::StateProperty;
+
+pub const AIR_NAME: &str = "air";
+
+// the definitions that are used if no other file has been loaded
+const DEFAULT_DEFINITIONS: &str = include_str!("../assets/blocks.txt");
+
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockProperties {
+    pub name: String,
+    pub solid: bool,
+    pub transparent: bool,
+    pub color: [f32; 4],
+    pub light_emission: u8,
+    pub hardness: f32,
+    pub collision: bool,
+    pub states: Vec<StateProperty>,
+    /// names of the textures of the top, side and bottom faces, see `BlockProperties::texture`
+    pub textures: [Option<String>; 3],
+    pub shape: BlockShape,
+}
+
+impl BlockProperties {
+    fn air() -> Self {
+        BlockProperties {
+            name: String::from(AIR_NAME),
+            solid: false,
+            transparent: true,
+            color: [0.0, 0.0, 0.0, 0.0],
+            light_emission: 0,
+            hardness: 0.0,
+            collision: false,
+            states: Vec::new(),
+            textures: [None, None, None],
+            // air has no geometry
+            shape: BlockShape::Boxes(Vec::new()),
+        }
+    }
+
+    /// the name of the texture of `face`, `None` if the face is filled with the block colour
+    pub fn texture(&self, face: Face) -> Option<&str> {
+        let slot = match face {
+            Face::YPos => 0,
+            Face::YNeg => 2,
+            _ => 1,
+        };
+        self.textures[slot].as_deref()
+    }
+
+    fn new(name: &str) -> Self {
+        BlockProperties {
+            name: String::from(name),
+            solid: true,
+            transparent: false,
+            color: [1.0, 1.0, 1.0, 1.0],
+            light_emission: 0,
+            hardness: 1.0,
+            collision: true,
+            states: Vec::new(),
+            textures: [None, None, None],
+            shape: BlockShape::Full,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    AlreadyLoaded,
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(e) => write!(f, "unable to read block definitions: {}", e),
+            RegistryError::Parse { line, message } => write!(f, "block definitions, line {}: {}", line, message),
+            RegistryError::AlreadyLoaded => write!(f, "the block registry has already been loaded"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<io::Error> for RegistryError {
+    fn from(e: io::Error) -> Self {
+        RegistryError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockRegistry {
+    // indexed by block id, `None` for ids that are not defined
+    blocks: Vec<Option<BlockProperties>>,
+    by_name: HashMap<String, BlockType>,
+}
+
+impl BlockRegistry {
+    pub fn parse(source: &str) -> Result<Self, RegistryError> {
+        let mut registry = BlockRegistry {
+            blocks: vec![Some(BlockProperties::air())],
+            by_name: HashMap::from([(String::from(AIR_NAME), BlockType::NO_BLOCK)]),
+        };
+
+        // the block that is currently being parsed, together with its id and the line of its header
+        let mut current: Option<(BlockProperties, Option<u16>, usize)> = None;
+        let mut collision_set = false;
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let error = |message: String| RegistryError::Parse { line: line_number, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if let Some(block) = current.take() {
+                    registry.add(block, collision_set)?;
+                }
+                current = Some((BlockProperties::new(name.trim()), None, line_number));
+                collision_set = false;
+                continue;
+            }
+
+            let Some((block, id, _)) = current.as_mut() else {
+                return Err(error(String::from("property outside of a block definition")));
+            };
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected `key = value`, found `{}`", line)));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "id" => *id = Some(value.parse().map_err(|_| error(format!("invalid id `{}`", value)))?),
+                "solid" => block.solid = parse_bool(value).ok_or_else(|| error(format!("invalid bool `{}`", value)))?,
+                "transparent" => block.transparent = parse_bool(value).ok_or_else(|| error(format!("invalid bool `{}`", value)))?,
+                "collision" => {
+                    block.collision = parse_bool(value).ok_or_else(|| error(format!("invalid bool `{}`", value)))?;
+                    collision_set = true;
+                },
+                "color" => block.color = parse_color(value).ok_or_else(|| error(format!("invalid color `{}`", value)))?,
+                "light_emission" => {
+                    block.light_emission = value.parse().ok().filter(|&l| l <= 15)
+                        .ok_or_else(|| error(format!("invalid light emission `{}`, expected 0 to 15", value)))?;
+                },
+                "states" => {
+                    block.states = value.split(',').map(str::trim).filter(|p| !p.is_empty())
+                        .map(|p| StateProperty::from_name(p).ok_or_else(|| error(format!("unknown state property `{}`", p))))
+                        .collect::<Result<_, _>>()?;
+                },
+                "texture" => {
+                    for slot in &mut block.textures {
+                        slot.get_or_insert_with(|| String::from(value));
+                    }
+                },
+                "texture_top" => block.textures[0] = Some(String::from(value)),
+                "texture_side" => block.textures[1] = Some(String::from(value)),
+                "texture_bottom" => block.textures[2] = Some(String::from(value)),
+                "shape" => block.shape = BlockShape::parse(value).map_err(error)?,
+                "hardness" => block.hardness = value.parse().map_err(|_| error(format!("invalid hardness `{}`", value)))?,
+                _ => return Err(error(format!("unknown property `{}`", key))),
+            }
+        }
+        if let Some(block) = current.take() {
+            registry.add(block, collision_set)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, RegistryError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn add(&mut self, (mut block, id, line): (BlockProperties, Option<u16>, usize), collision_set: bool) -> Result<(), RegistryError> {
+        let error = |message: String| RegistryError::Parse { line, message };
+        let id = id.ok_or_else(|| error(format!("block `{}` has no id", block.name)))?;
+        if id == BlockType::NO_BLOCK.0 {
+            return Err(error(format!("id 0 of block `{}` is reserved for air", block.name)));
+        }
+        if self.by_name.contains_key(&block.name) {
+            return Err(error(format!("block `{}` is defined twice", block.name)));
+        }
+        if self.blocks.len() <= id as usize {
+            self.blocks.resize(id as usize + 1, None);
+        }
+        if let Some(other) = &self.blocks[id as usize] {
+            return Err(error(format!("id {} of block `{}` is already used by `{}`", id, block.name, other.name)));
+        }
+
+        if !collision_set {
+            block.collision = block.solid;
+        }
+        self.by_name.insert(block.name.clone(), BlockType(id));
+        self.blocks[id as usize] = Some(block);
+        Ok(())
+    }
+
+    pub fn get(&self, block: BlockType) -> Option<&BlockProperties> {
+        self.blocks.get(block.0 as usize)?.as_ref()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        self.by_name.get(name).copied()
+    }
+
+    /// all defined block types, ordered by id
+    pub fn block_types(&self) -> impl Iterator<Item = BlockType> + '_ {
+        self.blocks.iter().enumerate().filter(|(_, b)| b.is_some()).map(|(id, _)| BlockType(id as u16))
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_color(s: &str) -> Option<[f32; 4]> {
+    let components: Vec<f32> = s.split_whitespace().map(|c| c.parse().ok()).collect::<Option<_>>()?;
+    match components[..] {
+        [r, g, b] => Some([r, g, b, 1.0]),
+        [r, g, b, a] => Some([r, g, b, a]),
+        _ => None,
+    }
+}
+
+/// loads the block definitions at `path` as the global registry. has to be called before the registry is used.
+pub fn load_registry(path: &Path) -> Result<(), RegistryError> {
+    let registry = BlockRegistry::load(path)?;
+    REGISTRY.set(registry).map_err(|_| RegistryError::AlreadyLoaded)
+}
+
+/// the global block registry. falls back to the built-in definitions if `load_registry` has not been called.
+pub fn registry() -> &'static BlockRegistry {
+    REGISTRY.get_or_init(|| BlockRegistry::parse(DEFAULT_DEFINITIONS).expect("built-in block definitions are invalid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the line and message of the error that parsing `source` fails with
+    fn parse_error(source: &str) -> (usize, String) {
+        match BlockRegistry::parse(source) {
+            Err(RegistryError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_built_in_definitions_are_valid() {
+        let registry = BlockRegistry::parse(DEFAULT_DEFINITIONS).unwrap();
+        assert_eq!(registry.by_name(AIR_NAME), Some(BlockType::NO_BLOCK));
+        let water = registry.get(registry.by_name("water").unwrap()).unwrap();
+        assert!(!water.solid && !water.collision && water.transparent);
+        assert_eq!(registry.block_types().next(), Some(BlockType::NO_BLOCK));
+    }
+
+    #[test]
+    fn properties_and_defaults() {
+        let registry = BlockRegistry::parse("[a]\nid = 3\ncolor = 0.5 0.25 1\nsolid = false\n\n# comment\n[b]\nid = 1\ncollision = false\n").unwrap();
+        let a = registry.get(BlockType(3)).unwrap();
+        assert_eq!(a.color, [0.5, 0.25, 1.0, 1.0]);
+        assert!(!a.collision);
+        let b = registry.get(BlockType(1)).unwrap();
+        assert!(b.solid && !b.collision && !b.transparent);
+        assert_eq!(b.hardness, 1.0);
+        assert_eq!(registry.get(BlockType(2)), None);
+        assert_eq!(registry.block_types().map(|b| b.0).collect::<Vec<_>>(), [0, 1, 3]);
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let (line, message) = parse_error("[a]\nid = 1\n\n[b]\nid = 1\n");
+        assert_eq!(line, 4);
+        assert_eq!(message, "id 1 of block `b` is already used by `a`");
+
+        let (line, message) = parse_error("[a]\nid = 1\n[a]\nid = 2\n");
+        assert_eq!(line, 3);
+        assert_eq!(message, "block `a` is defined twice");
+
+        let (line, message) = parse_error("[air]\nid = 5\n");
+        assert_eq!(line, 1);
+        assert_eq!(message, "block `air` is defined twice");
+    }
+
+    #[test]
+    fn id_0_is_reserved_for_air() {
+        let (line, message) = parse_error("[a]\nid = 1\n[b]\nid = 0\n");
+        assert_eq!(line, 3);
+        assert_eq!(message, "id 0 of block `b` is reserved for air");
+    }
+
+    #[test]
+    fn malformed_values_report_their_line() {
+        assert_eq!(parse_error("[a]\nid = 1\ncolor = 1 0\n"), (3, String::from("invalid color `1 0`")));
+        assert_eq!(parse_error("[a]\nid = 1\ncolor = 1 0 x 1\n").0, 3);
+        assert_eq!(parse_error("[a]\nid = 1\ncolor = 1 0 0 1 1\n").0, 3);
+        assert_eq!(parse_error("# x\n\n[a]\n  solid = yes\n"), (4, String::from("invalid bool `yes`")));
+        assert_eq!(parse_error("[a]\nid = 1\nlight_emission = 16\n").0, 3);
+        assert_eq!(parse_error("[a]\nid = 70000\n"), (2, String::from("invalid id `70000`")));
+        assert_eq!(parse_error("[a]\nid = 1\nweight = 3\n"), (3, String::from("unknown property `weight`")));
+        assert_eq!(parse_error("[a]\nid\n").0, 2);
+        assert_eq!(parse_error("id = 1\n"), (1, String::from("property outside of a block definition")));
+        assert_eq!(parse_error("[a]\nid = 1\n\n[b]\nsolid = true\n"), (4, String::from("block `b` has no id")));
+    }
+
+    #[test]
+    fn malformed_shapes_report_their_line() {
+        assert_eq!(parse_error("[a]\nid = 1\nshape = round\n"), (3, String::from("unknown shape `round`")));
+        // too few corners, a box reaching outside of the voxel and an empty box
+        assert_eq!(parse_error("[a]\nid = 1\nshape = boxes 0 0 0 16 16\n").0, 3);
+        assert_eq!(parse_error("[a]\nid = 1\nsolid = true\nshape = boxes 0 0 0 16 8 16, 0 8 0 8 17 16\n").0, 4);
+        assert_eq!(parse_error("[a]\nid = 1\nshape = boxes 4 0 4 4 16 12\n").0, 3);
+        assert_eq!(parse_error("[a]\nid = 1\nshape = boxes 0 0 0 16 x 16\n").0, 3);
+    }
+}
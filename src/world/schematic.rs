@@ -0,0 +1,293 @@
+// schematic files store a box of blocks that can be pasted into any world
+//
+// layout (all integers little endian):
+// ```text
+// magic "CTRS" | version u32
+// size: x u32 | y u32 | z u32
+// number of metadata entries u32
+// metadata entries: (key length u16, key utf-8, value length u32, value utf-8)
+// number of palette entries u32
+// palette entries: (name length u16, block name utf-8, state data u16)
+// bits per index u32 (0 if the palette has a single entry)
+// indices into the palette, packed into u64 words in the order given by `Size3D::c1d`
+// ```
+// blocks are stored by name, so schematics stay valid when block ids change.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::config;
+use super::block::BlockType;
+use super::palette::{read_packed, write_packed};
+use super::persistence::{invalid_data, read_u16, read_u32, write_u16, write_u32};
+use super::region::Clipboard;
+use super::size::Size3D;
+use super::state::BlockState;
+
+pub const SCHEMATIC_VERSION: u32 = 1;
+pub const SCHEMATIC_EXTENSION: &str = "cschem";
+
+const SCHEMATIC_MAGIC: &[u8; 4] = b"CTRS";
+
+/// a box of blocks together with free form metadata, e.g. `name` or `author`
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    pub blocks: Clipboard,
+    pub metadata: BTreeMap<String, String>,
+}
+
+fn write_string(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_u16(w, s.len() as u16)?;
+    w.write_all(s.as_bytes())
+}
+
+/// reads exactly `len` bytes. the buffer only grows with the data that is actually there,
+/// so a corrupt length does not allocate more than the rest of the file.
+fn read_bytes(r: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(buf)
+}
+
+fn read_string(r: &mut impl Read, len: usize) -> io::Result<String> {
+    String::from_utf8(read_bytes(r, len as u64)?).map_err(|e| invalid_data(e.to_string()))
+}
+
+/// smallest power of two number of bits that can index `n` palette entries
+fn bits_per_index(n: usize) -> u32 {
+    let mut bits = 0;
+    while (1 << bits) < n {
+        bits = if bits == 0 { 1 } else { 2 * bits };
+    }
+    bits
+}
+
+impl Schematic {
+    pub fn new(blocks: Clipboard) -> Self {
+        Schematic { blocks, metadata: BTreeMap::new() }
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let size = self.blocks.size();
+        w.write_all(SCHEMATIC_MAGIC)?;
+        write_u32(w, SCHEMATIC_VERSION)?;
+        for d in [size.x, size.y, size.z] {
+            write_u32(w, d as u32)?;
+        }
+
+        write_u32(w, self.metadata.len() as u32)?;
+        for (key, value) in &self.metadata {
+            write_string(w, key)?;
+            write_u32(w, value.len() as u32)?;
+            w.write_all(value.as_bytes())?;
+        }
+
+        let mut palette: Vec<BlockState> = Vec::new();
+        let mut palette_index: HashMap<BlockState, usize> = HashMap::new();
+        let indices: Vec<usize> = size.into_iter().map(|c| {
+            let block = self.blocks.get(c);
+            *palette_index.entry(block).or_insert_with(|| {
+                palette.push(block);
+                palette.len() - 1
+            })
+        }).collect();
+
+        write_u32(w, palette.len() as u32)?;
+        for block in &palette {
+            write_string(w, block.block.name())?;
+            write_u16(w, block.data())?;
+        }
+
+        let bits = bits_per_index(palette.len());
+        write_u32(w, bits)?;
+        // a single palette entry needs no index data
+        if let Some(per_word) = u64::BITS.checked_div(bits) {
+            let mut data = vec![0u64; indices.len().div_ceil(per_word as usize)];
+            for (i, &index) in indices.iter().enumerate() {
+                write_packed(&mut data, bits, i, index);
+            }
+            for word in data {
+                w.write_all(&word.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != SCHEMATIC_MAGIC {
+            return Err(invalid_data(format!("bad magic {:?}, expected {:?}", magic, SCHEMATIC_MAGIC)));
+        }
+        let version = read_u32(r)?;
+        if version != SCHEMATIC_VERSION {
+            return Err(invalid_data(format!("unsupported schematic version {}", version)));
+        }
+        let size = Size3D { x: read_u32(r)? as u64, y: read_u32(r)? as u64, z: read_u32(r)? as u64 };
+        // the header is not trusted, nothing is allocated for the blocks before their number has been checked
+        let volume = size.x.checked_mul(size.y).and_then(|v| v.checked_mul(size.z));
+        match volume {
+            Some(0) => return Err(invalid_data(format!("empty schematic of size {:?}", size))),
+            Some(v) if v <= config::MAX_EDIT_VOLUME => (),
+            _ => return Err(invalid_data(format!("schematic of size {:?} is larger than {} blocks", size, config::MAX_EDIT_VOLUME))),
+        }
+
+        let mut metadata = BTreeMap::new();
+        for _ in 0..read_u32(r)? {
+            let key_len = read_u16(r)? as usize;
+            let key = read_string(r, key_len)?;
+            let value_len = read_u32(r)? as usize;
+            metadata.insert(key, read_string(r, value_len)?);
+        }
+
+        let n_palette = read_u32(r)? as usize;
+        if n_palette == 0 {
+            return Err(invalid_data(String::from("empty palette")));
+        }
+        // every palette entry is used by at least one block
+        if n_palette as u64 > size.volume() {
+            return Err(invalid_data(format!("{} palette entries for {} blocks", n_palette, size.volume())));
+        }
+        let mut palette = Vec::new();
+        for _ in 0..n_palette {
+            let name_len = read_u16(r)? as usize;
+            let name = read_string(r, name_len)?;
+            let block = BlockType::from_name(&name).ok_or_else(|| invalid_data(format!("unknown block type `{}`", name)))?;
+            palette.push(BlockState::from_raw(block, read_u16(r)?));
+        }
+
+        let bits = read_u32(r)?;
+        if bits != bits_per_index(n_palette) {
+            return Err(invalid_data(format!("{} bits per index for {} palette entries", bits, n_palette)));
+        }
+        let mut blocks = Clipboard::new(size);
+        if let Some(per_word) = u64::BITS.checked_div(bits) {
+            let n_words = size.volume().div_ceil(per_word as u64);
+            let data: Vec<u64> = read_bytes(r, 8 * n_words)?.chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect();
+            for (i, c) in size.into_iter().enumerate() {
+                let index = read_packed(&data, bits, i);
+                let block = *palette.get(index).ok_or_else(|| invalid_data(format!("palette index {} out of range", index)))?;
+                blocks.set(c, block);
+            }
+        } else {
+            blocks.fill(palette[0]);
+        }
+        Ok(Schematic { blocks, metadata })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{World, Face, icoords::ICoords, region::Region, state::Half};
+
+    fn block(name: &str) -> BlockState {
+        BlockState::new(BlockType::from_name(name).unwrap())
+    }
+
+    fn round_trip(schematic: &Schematic) -> io::Result<Schematic> {
+        let mut bytes = Vec::new();
+        schematic.write(&mut bytes)?;
+        Schematic::read(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn round_trip_through_the_world() {
+        let mut world = World::new();
+        // a box around the origin that spans eight l1 segments
+        let region = Region::new(ICoords::new(-3, -2, -4), ICoords::new(2, 3, 1));
+        let furnace = block("furnace").with_facing(Face::ZNeg);
+        let slab = block("stone_slab").with_half(Half::Top);
+        let pattern = |c: ICoords| match (c.x + 2 * c.y + 3 * c.z).rem_euclid(4) {
+            0 => slab,
+            1 => furnace,
+            2 => block("glass"),
+            _ => BlockState::NO_BLOCK,
+        };
+        for c in region.size() {
+            world.set_block(region.min + c, pattern(region.min + c)).unwrap();
+        }
+
+        let mut schematic = Schematic::new(world.copy(region).unwrap());
+        schematic.metadata.insert(String::from("name"), String::from("test"));
+        let read = round_trip(&schematic).unwrap();
+        assert_eq!(read.metadata, schematic.metadata);
+        assert_eq!(read.blocks.size(), region.size());
+
+        // pasted so that it crosses the l1 boundaries at -32 and 0 on every axis
+        let origin = ICoords::new(-34, -33, -35);
+        world.paste(&read.blocks, origin, true).unwrap();
+        for c in region.size() {
+            assert_eq!(world.get_block(origin + c), pattern(region.min + c), "{:?}", origin + c);
+        }
+        // nothing is pasted next to the box
+        assert_eq!(world.get_block(origin - ICoords::new(1, 0, 0)), BlockState::NO_BLOCK);
+    }
+
+    #[test]
+    fn uniform_schematic_has_no_index_data() {
+        let mut blocks = Clipboard::new(Size3D { x: 5, y: 1, z: 3 });
+        blocks.fill(block("stone"));
+        let mut bytes = Vec::new();
+        Schematic::new(blocks).write(&mut bytes).unwrap();
+        // header, size, no metadata, one palette entry, 0 bits per index
+        assert_eq!(bytes.len(), 8 + 12 + 4 + 4 + 2 + "stone".len() + 2 + 4);
+        let read = Schematic::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.blocks.get(ICoords::new(4, 0, 2)), block("stone"));
+    }
+
+    /// a schematic header with the given size, followed by `rest`
+    fn header(size: [u32; 3], rest: &[u32]) -> Vec<u8> {
+        let mut bytes = SCHEMATIC_MAGIC.to_vec();
+        for v in [SCHEMATIC_VERSION].iter().chain(&size).chain(rest) {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn read_error(bytes: &[u8]) -> io::ErrorKind {
+        Schematic::read(&mut &bytes[..]).expect_err("corrupt schematic was read").kind()
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected_before_allocating() {
+        // the volume overflows a u64
+        assert_eq!(read_error(&header([u32::MAX; 3], &[0, 1])), io::ErrorKind::InvalidData);
+        // larger than a clipboard may be
+        assert_eq!(read_error(&header([1 << 16, 1 << 16, 1], &[0, 1])), io::ErrorKind::InvalidData);
+        assert_eq!(read_error(&header([0, 1, 1], &[0, 1])), io::ErrorKind::InvalidData);
+        // more palette entries than blocks
+        assert_eq!(read_error(&header([2, 2, 2], &[0, u32::MAX])), io::ErrorKind::InvalidData);
+        // a metadata value of 4 GiB in a short file
+        let mut bytes = header([2, 2, 2], &[1]);
+        bytes.extend_from_slice(&[1, 0, b'k']);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_error(&bytes), io::ErrorKind::UnexpectedEof);
+        // index data that ends early
+        let mut bytes = header([64, 64, 64], &[0, 2]);
+        for name in ["stone", "glass"] {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        assert_eq!(read_error(&bytes), io::ErrorKind::UnexpectedEof);
+    }
+}
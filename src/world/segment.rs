@@ -1,27 +1,111 @@
 use crate::world::*;
 use crate::world::size::*;
+use crate::world::palette::PalettedVec;
+use crate::world::state::BlockState;
 
 pub const L1_SIZE_BITS: u64 = 5;
 pub const L1_SIZE: Size3D = Size3D { x: 1 << L1_SIZE_BITS, y: 1 << L1_SIZE_BITS, z: 1 << L1_SIZE_BITS };
 pub const L1_SIZE_BL: Size3D = L1_SIZE;
 
+/// the blocks of an L1 segment are stored palette compressed, see `PalettedVec`
 #[derive(Clone, Debug)]
 pub struct L1Segment {
-    pub blocks: Vec<BlockType>,
+    blocks: PalettedVec<BlockState>,
+    /// light level of every block, packed as in `light::pack`. most segments are uniformly lit or dark.
+    light: PalettedVec<u8>,
 }
 
 impl L1Segment {
+    /// - `coords`: local coordinates of the block within the segment
+    pub fn get(&self, coords: ICoords) -> BlockState {
+        self.blocks.get(L1_SIZE_BL.c1d(coords) as usize)
+    }
+
+    /// sets the block at local coordinates `coords` and returns the previous block
+    pub fn set(&mut self, coords: ICoords, block: BlockState) -> BlockState {
+        self.blocks.set(L1_SIZE_BL.c1d(coords) as usize, block)
+    }
+
+    /// - `i`: index of the block as given by `L1_SIZE_BL.c1d`
+    pub fn get_index(&self, i: usize) -> BlockState {
+        self.blocks.get(i)
+    }
+
+    pub fn set_index(&mut self, i: usize, block: BlockState) -> BlockState {
+        self.blocks.set(i, block)
+    }
+
+    pub fn fill(&mut self, block: BlockState) {
+        self.blocks.fill(block);
+    }
+
+    /// a copy of the compressed blocks, e.g. to restore them later with `swap_blocks`
+    pub fn blocks_image(&self) -> PalettedVec<BlockState> {
+        self.blocks.clone()
+    }
+
+    /// exchanges the blocks of the segment with `blocks`, which has to have the length of a segment
+    pub fn swap_blocks(&mut self, blocks: &mut PalettedVec<BlockState>) {
+        assert_eq!(blocks.len(), self.blocks.len(), "the blocks do not have the size of an l1 segment");
+        std::mem::swap(&mut self.blocks, blocks);
+    }
+
+    /// all blocks in the order given by `L1_SIZE_BL.c1d`
+    pub fn blocks(&self) -> impl Iterator<Item = BlockState> + '_ {
+        self.blocks.iter()
+    }
+
+    /// returns `Some(block)` if the segment consists of `block` only
+    pub fn uniform(&self) -> Option<BlockState> {
+        self.blocks.uniform()
+    }
+
+    /// whether at least one block of the segment is of type `block`
+    pub fn contains_block_type(&self, block: BlockType) -> bool {
+        self.blocks.palette().any(|(b, _)| b.block == block)
+    }
+
+    /// - `coords`: local coordinates of the block within the segment
+    pub fn light(&self, coords: ICoords) -> u8 {
+        self.light.get(L1_SIZE_BL.c1d(coords) as usize)
+    }
+
+    /// sets the packed light level at local coordinates `coords` and returns the previous level
+    pub fn set_light(&mut self, coords: ICoords, light: u8) -> u8 {
+        self.light.set(L1_SIZE_BL.c1d(coords) as usize, light)
+    }
+
+    /// replaces the light levels of all blocks, in the order given by `L1_SIZE_BL.c1d`
+    pub fn set_lights(&mut self, lights: &[u8]) {
+        if lights.iter().all(|&l| l == lights[0]) {
+            self.light.fill(lights[0]);
+            return;
+        }
+        for (i, &l) in lights.iter().enumerate() {
+            self.light.set(i, l);
+        }
+    }
+
     pub fn number_of_solid_blocks(&self) -> usize {
-        self.blocks.iter().filter(|&t| t != &BlockType::NoBlock).collect::<Vec<_>>().len()
+        self.blocks.palette().filter(|(t, _)| t.is_solid()).map(|(_, n)| n).sum()
+    }
+
+    /// approximate number of bytes used by the segment
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.blocks.memory_usage() + self.light.memory_usage()
+    }
+
+    /// approximate number of bytes used by the blocks, which is the size of `blocks_image`
+    pub fn blocks_memory_usage(&self) -> usize {
+        self.blocks.memory_usage()
     }
 }
 
 impl Default for L1Segment {
     fn default() -> Self {
-        let mut blocks = Vec::new();
-        blocks.resize(L1_SIZE.volume() as usize, BlockType::NoBlock);
         L1Segment {
-            blocks,
+            blocks: PalettedVec::new(L1_SIZE.volume() as usize, BlockState::NO_BLOCK),
+            light: PalettedVec::new(L1_SIZE.volume() as usize, 0),
         }
     }
 }
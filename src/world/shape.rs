@@ -0,0 +1,263 @@
+// block shapes: the geometry of a block within its voxel as a list of axis aligned boxes.
+// coordinates are in sixteenths of a block, so that the sides of different shapes can be compared exactly
+// when deciding whether a face is covered by its neighbour.
+
+use super::Face;
+use super::state::{BlockState, Half};
+
+/// number of shape units along each edge of a block
+pub const SHAPE_UNITS: u8 = 16;
+
+/// an axis aligned box within a voxel, in shape units from its 0 0 0 corner
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ShapeBox {
+    pub min: [u8; 3],
+    pub max: [u8; 3],
+}
+
+impl ShapeBox {
+    /// the whole voxel
+    pub const FULL: ShapeBox = ShapeBox { min: [0; 3], max: [SHAPE_UNITS; 3] };
+
+    /// `None` if the box is empty or reaches outside of the voxel
+    pub fn new(min: [u8; 3], max: [u8; 3]) -> Option<Self> {
+        (0..3).all(|i| min[i] < max[i] && max[i] <= SHAPE_UNITS).then_some(ShapeBox { min, max })
+    }
+
+    /// the coordinate of the side of the box towards `face` along the axis of the face
+    pub fn side(&self, face: Face) -> u8 {
+        let n = normal_axis(face);
+        if is_positive(face) { self.max[n] } else { self.min[n] }
+    }
+
+    /// the cells covered by the side of the box towards `face`
+    pub fn face_mask(&self, face: Face) -> FaceMask {
+        let (u, v) = plane_axes(face);
+        let bits = ((1u32 << self.max[v]) - (1u32 << self.min[v])) as u16;
+        let mut mask = FaceMask::EMPTY;
+        for row in &mut mask.0[self.min[u] as usize..self.max[u] as usize] {
+            *row = bits;
+        }
+        mask
+    }
+
+    fn mirrored_y(self) -> Self {
+        let [x0, y0, z0] = self.min;
+        let [x1, y1, z1] = self.max;
+        ShapeBox { min: [x0, SHAPE_UNITS - y1, z0], max: [x1, SHAPE_UNITS - y0, z1] }
+    }
+
+    /// the box after `turns` quarter turns around the vertical centre line of the voxel, from x towards z
+    fn rotated_y(self, turns: u32) -> Self {
+        let mut b = self;
+        for _ in 0..turns % 4 {
+            // x z -> 16 - z x
+            b = ShapeBox {
+                min: [SHAPE_UNITS - b.max[2], b.min[1], b.min[0]],
+                max: [SHAPE_UNITS - b.min[2], b.max[1], b.max[0]],
+            };
+        }
+        b
+    }
+}
+
+/// which of the 16x16 cells of a plane perpendicular to a face direction are covered.
+/// the rows run along the axis following the axis of the face, the bits along the one after it (x: y z, y: z x, z: x y),
+/// so that the masks of opposite faces can be compared directly.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FaceMask(pub [u16; SHAPE_UNITS as usize]);
+
+impl FaceMask {
+    pub const EMPTY: FaceMask = FaceMask([0; SHAPE_UNITS as usize]);
+    pub const FULL: FaceMask = FaceMask([u16::MAX; SHAPE_UNITS as usize]);
+
+    pub fn union(mut self, other: FaceMask) -> FaceMask {
+        for (row, other) in self.0.iter_mut().zip(other.0) {
+            *row |= other;
+        }
+        self
+    }
+
+    /// whether every cell of `other` is covered by `self`
+    pub fn covers(&self, other: &FaceMask) -> bool {
+        self.0.iter().zip(other.0).all(|(row, other)| other & !row == 0)
+    }
+}
+
+/// the shape of a block type, set with `shape = ...` in the block definitions (see `world::registry`)
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum BlockShape {
+    /// the whole voxel
+    #[default]
+    Full,
+    /// the lower or upper half of the voxel, by the `half` state property
+    Slab,
+    /// a slab with a step on the side its `facing` points to, upside down for the upper `half`
+    Stairs,
+    /// two crossed diagonal quads, e.g. for plants. covers no part of the faces of the voxel.
+    Cross,
+    /// any number of boxes. they are rotated by `facing` and mirrored by `half` like stairs.
+    Boxes(Vec<ShapeBox>),
+}
+
+impl BlockShape {
+    /// parses the value of `shape = ...`: `full`, `slab`, `stairs`, `cross` or
+    /// `boxes x0 y0 z0 x1 y1 z1, ...` with the opposite corners of each box in shape units
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "full" => return Ok(BlockShape::Full),
+            "slab" => return Ok(BlockShape::Slab),
+            "stairs" => return Ok(BlockShape::Stairs),
+            "cross" => return Ok(BlockShape::Cross),
+            _ => (),
+        }
+        let Some(boxes) = s.strip_prefix("boxes") else {
+            return Err(format!("unknown shape `{}`", s));
+        };
+        boxes.split(',').map(str::trim).filter(|b| !b.is_empty()).map(|b| {
+            let invalid = || format!("invalid box `{}`, expected `x0 y0 z0 x1 y1 z1` between 0 and {}", b, SHAPE_UNITS);
+            let c: Vec<u8> = b.split_whitespace().map(|c| c.parse().ok()).collect::<Option<_>>().ok_or_else(invalid)?;
+            match c[..] {
+                [x0, y0, z0, x1, y1, z1] => ShapeBox::new([x0, y0, z0], [x1, y1, z1]).ok_or_else(invalid),
+                _ => Err(invalid()),
+            }
+        }).collect::<Result<_, _>>().map(BlockShape::Boxes)
+    }
+
+    /// the boxes of a block of this shape in the state `state`
+    pub fn boxes(&self, state: BlockState) -> Vec<ShapeBox> {
+        let half = state.half();
+        let boxes = match self {
+            BlockShape::Full => return vec![ShapeBox::FULL],
+            BlockShape::Cross => return Vec::new(),
+            BlockShape::Slab | BlockShape::Stairs if half == Half::Double => return vec![ShapeBox::FULL],
+            BlockShape::Slab => vec![ShapeBox { min: [0, 0, 0], max: [16, 8, 16] }],
+            BlockShape::Stairs => vec![
+                ShapeBox { min: [0, 0, 0], max: [16, 8, 16] },
+                ShapeBox { min: [8, 8, 0], max: [16, 16, 16] },
+            ],
+            BlockShape::Boxes(boxes) => boxes.clone(),
+        };
+        // the boxes are defined facing towards x and in the lower half
+        let turns = match state.facing() {
+            Face::ZPos => 1,
+            Face::XNeg => 2,
+            Face::ZNeg => 3,
+            _ => 0,
+        };
+        boxes.into_iter()
+            .map(|b| if half == Half::Top { b.mirrored_y() } else { b })
+            .map(|b| b.rotated_y(turns))
+            .collect()
+    }
+}
+
+/// the cells of the plane at `side` (in shape units along the axis of `face`) covered by the sides towards `face`
+/// of those `boxes` that end in the plane
+pub fn coverage(boxes: &[ShapeBox], face: Face, side: u8) -> FaceMask {
+    boxes.iter()
+        .filter(|b| b.side(face) == side)
+        .fold(FaceMask::EMPTY, |mask, b| mask.union(b.face_mask(face)))
+}
+
+/// the side of the voxel towards `face`, in shape units along the axis of the face
+pub fn voxel_side(face: Face) -> u8 {
+    if is_positive(face) { SHAPE_UNITS } else { 0 }
+}
+
+/// the index of the axis of `face` in x y z
+pub fn normal_axis(face: Face) -> usize {
+    match face {
+        Face::XPos | Face::XNeg => 0,
+        Face::YPos | Face::YNeg => 1,
+        Face::ZPos | Face::ZNeg => 2,
+    }
+}
+
+/// the axes along the rows and the bits of a `FaceMask` of `face`
+fn plane_axes(face: Face) -> (usize, usize) {
+    let n = normal_axis(face);
+    ((n + 1) % 3, (n + 2) % 3)
+}
+
+fn is_positive(face: Face) -> bool {
+    matches!(face, Face::XPos | Face::YPos | Face::ZPos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::BlockType;
+
+    fn block(name: &str) -> BlockState {
+        BlockState::new(BlockType::from_name(name).unwrap())
+    }
+
+    /// the cells of a side face that are covered by a bottom slab
+    fn lower_half(face: Face) -> FaceMask {
+        ShapeBox { min: [0, 0, 0], max: [16, 8, 16] }.face_mask(face)
+    }
+
+    #[test]
+    fn boxes_are_parsed() {
+        assert_eq!(BlockShape::parse("stairs"), Ok(BlockShape::Stairs));
+        let boxes = vec![ShapeBox::new([0, 0, 0], [16, 8, 16]).unwrap(), ShapeBox::new([0, 8, 0], [8, 16, 16]).unwrap()];
+        assert_eq!(BlockShape::parse("boxes 0 0 0 16 8 16, 0 8 0 8 16 16"), Ok(BlockShape::Boxes(boxes)));
+        assert!(BlockShape::parse("boxes 0 0 0 16 8").is_err());
+        assert!(BlockShape::parse("boxes 8 0 0 8 8 16").is_err());
+    }
+
+    #[test]
+    fn stairs_face_each_direction() {
+        let stairs = block("stone_stairs");
+        for facing in [Face::XPos, Face::ZPos, Face::XNeg, Face::ZNeg] {
+            let state = stairs.with_facing(facing);
+            // the step closes the side the stairs face, the opposite side is only closed by the lower half
+            assert_eq!(state.face_coverage(facing), FaceMask::FULL, "{:?}", facing);
+            assert_eq!(state.face_coverage(facing.opposite()), lower_half(facing.opposite()), "{:?}", facing);
+            assert_eq!(state.face_coverage(Face::YNeg), FaceMask::FULL, "{:?}", facing);
+
+            let step = state.boxes().into_iter().find(|b| b.min[1] == 8).unwrap();
+            assert_eq!(step.side(facing), voxel_side(facing), "{:?}", facing);
+            assert_eq!(step.side(facing.opposite()), SHAPE_UNITS / 2, "{:?}", facing);
+            assert_eq!(state.face_coverage(Face::YPos), step.face_mask(Face::YPos), "{:?}", facing);
+        }
+    }
+
+    #[test]
+    fn upper_halves_are_mirrored() {
+        let slab = block("stone_slab");
+        let stairs = block("stone_stairs").with_facing(Face::ZNeg);
+        for bottom in [slab, stairs] {
+            let top = bottom.with_half(Half::Top);
+            let mirrored: Vec<_> = bottom.boxes().into_iter().map(ShapeBox::mirrored_y).collect();
+            assert_eq!(top.boxes(), mirrored);
+            assert_eq!(top.face_coverage(Face::YPos), FaceMask::FULL);
+            assert_eq!(top.face_coverage(Face::YNeg), bottom.face_coverage(Face::YPos));
+            for face in [Face::XPos, Face::XNeg, Face::ZPos, Face::ZNeg] {
+                let mirrored = coverage(&mirrored, face, voxel_side(face));
+                assert_eq!(top.face_coverage(face), mirrored, "{:?}", face);
+            }
+        }
+        assert_eq!(slab.with_half(Half::Double).boxes(), vec![ShapeBox::FULL]);
+    }
+
+    #[test]
+    fn slabs_cover_the_faces_of_slabs_of_the_same_half() {
+        let bottom = block("stone_slab");
+        let top = bottom.with_half(Half::Top);
+        let double = bottom.with_half(Half::Double);
+        // side by side
+        assert!(bottom.face_coverage(Face::XNeg).covers(&bottom.face_coverage(Face::XPos)));
+        assert!(top.face_coverage(Face::ZPos).covers(&top.face_coverage(Face::ZNeg)));
+        assert!(!top.face_coverage(Face::XNeg).covers(&bottom.face_coverage(Face::XPos)));
+        assert!(!bottom.face_coverage(Face::XNeg).covers(&top.face_coverage(Face::XPos)));
+        assert!(double.face_coverage(Face::XNeg).covers(&top.face_coverage(Face::XPos)));
+        assert!(!bottom.face_coverage(Face::XNeg).covers(&double.face_coverage(Face::XPos)));
+        // stacked, the top of a bottom slab lies inside its voxel and is never covered by the slab above
+        assert_eq!(bottom.face_coverage(Face::YPos), FaceMask::EMPTY);
+        assert!(!bottom.face_coverage(Face::YPos).covers(&bottom.face_coverage(Face::YNeg)));
+        assert!(top.face_coverage(Face::YPos).covers(&bottom.face_coverage(Face::YNeg)));
+        assert_eq!(top.face_coverage(Face::YNeg), FaceMask::EMPTY);
+    }
+}
@@ -18,7 +18,7 @@ impl Iterator for Size3DIterator {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Size3D {
     pub x: u64,
     pub y: u64,
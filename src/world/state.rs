@@ -0,0 +1,350 @@
+use std::fmt;
+use std::str::FromStr;
+use super::{Axis, Face};
+use super::block::BlockType;
+use super::shape::{self, BlockShape, FaceMask, ShapeBox};
+
+// layout of `BlockState::data`
+const FACING_SHIFT: u16 = 0;
+const FACING_MASK: u16 = 0b111;
+const AXIS_SHIFT: u16 = 3;
+const AXIS_MASK: u16 = 0b11;
+const HALF_SHIFT: u16 = 5;
+const HALF_MASK: u16 = 0b11;
+const VARIANT_SHIFT: u16 = 7;
+const VARIANT_MASK: u16 = 0b1111;
+
+/// the properties a block type can declare in its definition with `states = ...`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum StateProperty {
+    /// direction the front (local `XPos`) of the block points to
+    Facing,
+    /// world axis the local y axis of the block is aligned with, e.g. for logs
+    Axis,
+    /// lower, upper or both halves of the voxel, e.g. for slabs
+    Half,
+    /// one of 16 variants of the same block type, e.g. colours
+    Variant,
+}
+
+impl StateProperty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateProperty::Facing => "facing",
+            StateProperty::Axis => "axis",
+            StateProperty::Half => "half",
+            StateProperty::Variant => "variant",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "facing" => Some(StateProperty::Facing),
+            "axis" => Some(StateProperty::Axis),
+            "half" => Some(StateProperty::Half),
+            "variant" => Some(StateProperty::Variant),
+            _ => None,
+        }
+    }
+
+    fn mask(&self) -> u16 {
+        match self {
+            StateProperty::Facing => FACING_MASK << FACING_SHIFT,
+            StateProperty::Axis => AXIS_MASK << AXIS_SHIFT,
+            StateProperty::Half => HALF_MASK << HALF_SHIFT,
+            StateProperty::Variant => VARIANT_MASK << VARIANT_SHIFT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum BlockAxis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Half {
+    #[default]
+    Bottom,
+    Top,
+    Double,
+}
+
+/// a block type together with the values of its state properties.
+///
+/// all properties are packed into 16 bits. properties that the block type does not declare are always
+/// at their default value, so that equal looking blocks compare equal and share a palette entry.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct BlockState {
+    pub block: BlockType,
+    data: u16,
+}
+
+impl BlockState {
+    pub const NO_BLOCK: BlockState = BlockState { block: BlockType::NO_BLOCK, data: 0 };
+
+    /// `block` with all properties at their default value
+    pub fn new(block: BlockType) -> Self {
+        BlockState { block, data: 0 }
+    }
+
+    /// `block` with the packed properties `data`, as returned by `data()`
+    pub fn from_raw(block: BlockType, data: u16) -> Self {
+        BlockState { block, data }.normalized()
+    }
+
+    pub fn data(&self) -> u16 {
+        self.data
+    }
+
+    /// resets all properties that are not declared by the block type
+    pub fn normalized(self) -> Self {
+        let mask = self.block.properties().states.iter().fold(0, |mask, p| mask | p.mask());
+        BlockState { block: self.block, data: self.data & mask }
+    }
+
+    pub fn has_property(&self, property: StateProperty) -> bool {
+        self.block.properties().states.contains(&property)
+    }
+
+    pub fn facing(&self) -> Face {
+        let bits = (self.data >> FACING_SHIFT) & FACING_MASK;
+        Face::all().get(bits as usize).copied().unwrap_or(Face::XPos)
+    }
+
+    pub fn axis(&self) -> BlockAxis {
+        match (self.data >> AXIS_SHIFT) & AXIS_MASK {
+            1 => BlockAxis::X,
+            2 => BlockAxis::Z,
+            _ => BlockAxis::Y,
+        }
+    }
+
+    pub fn half(&self) -> Half {
+        match (self.data >> HALF_SHIFT) & HALF_MASK {
+            1 => Half::Top,
+            2 => Half::Double,
+            _ => Half::Bottom,
+        }
+    }
+
+    pub fn variant(&self) -> u8 {
+        ((self.data >> VARIANT_SHIFT) & VARIANT_MASK) as u8
+    }
+
+    // the setters do not check whether the block type declares the property,
+    // undeclared properties are reset when the state is stored in a segment.
+
+    pub fn with_facing(self, facing: Face) -> Self {
+        self.with_bits(FACING_SHIFT, FACING_MASK, facing as u16)
+    }
+
+    pub fn with_axis(self, axis: BlockAxis) -> Self {
+        let bits = match axis {
+            BlockAxis::Y => 0,
+            BlockAxis::X => 1,
+            BlockAxis::Z => 2,
+        };
+        self.with_bits(AXIS_SHIFT, AXIS_MASK, bits)
+    }
+
+    pub fn with_half(self, half: Half) -> Self {
+        let bits = match half {
+            Half::Bottom => 0,
+            Half::Top => 1,
+            Half::Double => 2,
+        };
+        self.with_bits(HALF_SHIFT, HALF_MASK, bits)
+    }
+
+    /// - `variant`: 0 to 15, higher bits are ignored
+    pub fn with_variant(self, variant: u8) -> Self {
+        self.with_bits(VARIANT_SHIFT, VARIANT_MASK, variant as u16)
+    }
+
+    fn with_bits(self, shift: u16, mask: u16, bits: u16) -> Self {
+        BlockState { block: self.block, data: (self.data & !(mask << shift)) | ((bits & mask) << shift) }
+    }
+
+    /// the state after rotating the block by `turns` quarter turns around the y axis, from x towards z
+    pub fn rotated_y(self, turns: u32) -> Self {
+        let mut state = self;
+        for _ in 0..turns % 4 {
+            let facing = match state.facing() {
+                Face::XPos => Face::ZPos,
+                Face::ZPos => Face::XNeg,
+                Face::XNeg => Face::ZNeg,
+                Face::ZNeg => Face::XPos,
+                vertical => vertical,
+            };
+            let axis = match state.axis() {
+                BlockAxis::X => BlockAxis::Z,
+                BlockAxis::Z => BlockAxis::X,
+                BlockAxis::Y => BlockAxis::Y,
+            };
+            state = state.with_facing(facing).with_axis(axis);
+        }
+        state.normalized()
+    }
+
+    /// the state after mirroring the block along `axis`
+    pub fn mirrored(self, axis: Axis) -> Self {
+        let facing = match (axis, self.facing()) {
+            (Axis::X, Face::XPos) => Face::XNeg,
+            (Axis::X, Face::XNeg) => Face::XPos,
+            (Axis::Y, Face::YPos) => Face::YNeg,
+            (Axis::Y, Face::YNeg) => Face::YPos,
+            (Axis::Z, Face::ZPos) => Face::ZNeg,
+            (Axis::Z, Face::ZNeg) => Face::ZPos,
+            (_, facing) => facing,
+        };
+        let half = match (axis, self.half()) {
+            (Axis::Y, Half::Bottom) => Half::Top,
+            (Axis::Y, Half::Top) => Half::Bottom,
+            (_, half) => half,
+        };
+        self.with_facing(facing).with_half(half).normalized()
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.block.is_solid()
+    }
+
+    /// whether the block stops light, see `world::light`. blocks that do not fill their voxel let light through.
+    pub fn is_opaque(&self) -> bool {
+        self.is_full() && !self.block.is_transparent()
+    }
+
+    /// whether the block covers its whole voxel, so that the faces of its neighbours that touch it are hidden
+    pub fn is_full(&self) -> bool {
+        self.is_solid() && self.is_cube()
+    }
+
+    /// whether the shape of the block is the whole voxel
+    pub fn is_cube(&self) -> bool {
+        match &self.block.properties().shape {
+            BlockShape::Full => true,
+            BlockShape::Slab | BlockShape::Stairs => self.half() == Half::Double,
+            BlockShape::Cross => false,
+            BlockShape::Boxes(boxes) => boxes[..] == [ShapeBox::FULL],
+        }
+    }
+
+    /// the boxes the block consists of, with its facing and half applied
+    pub fn boxes(&self) -> Vec<ShapeBox> {
+        self.block.properties().shape.boxes(*self)
+    }
+
+    /// the cells of the side of the voxel towards `face` that are covered by the block
+    pub fn face_coverage(&self, face: Face) -> FaceMask {
+        if self.is_cube() {
+            return FaceMask::FULL;
+        }
+        shape::coverage(&self.boxes(), face, shape::voxel_side(face))
+    }
+
+    /// the face of the block in its unrotated orientation that is seen from direction `world_face`.
+    /// `facing` rotates the local `XPos` face onto the facing direction, `axis` rotates the local `YPos` face onto the axis.
+    pub fn local_face(&self, world_face: Face) -> Face {
+        use Face::*;
+        // local face for each world face, in the order of `Face::all`
+        let table = if self.has_property(StateProperty::Axis) {
+            match self.axis() {
+                BlockAxis::Y => [XPos, XNeg, YPos, YNeg, ZPos, ZNeg],
+                BlockAxis::X => [YPos, YNeg, XNeg, XPos, ZPos, ZNeg],
+                BlockAxis::Z => [XPos, XNeg, ZNeg, ZPos, YPos, YNeg],
+            }
+        } else if self.has_property(StateProperty::Facing) {
+            match self.facing() {
+                XPos => [XPos, XNeg, YPos, YNeg, ZPos, ZNeg],
+                XNeg => [XNeg, XPos, YPos, YNeg, ZNeg, ZPos],
+                YPos => [YNeg, YPos, XPos, XNeg, ZPos, ZNeg],
+                YNeg => [YPos, YNeg, XNeg, XPos, ZPos, ZNeg],
+                ZPos => [ZNeg, ZPos, YPos, YNeg, XPos, XNeg],
+                ZNeg => [ZPos, ZNeg, YPos, YNeg, XNeg, XPos],
+            }
+        } else {
+            return world_face;
+        };
+        table[world_face as usize]
+    }
+}
+
+impl From<BlockType> for BlockState {
+    fn from(block: BlockType) -> Self {
+        BlockState::new(block)
+    }
+}
+
+fn face_name(face: Face) -> &'static str {
+    match face {
+        Face::XPos => "+x",
+        Face::XNeg => "-x",
+        Face::YPos => "+y",
+        Face::YNeg => "-y",
+        Face::ZPos => "+z",
+        Face::ZNeg => "-z",
+    }
+}
+
+/// formats the state as `name[property=value,...]`, listing the declared properties only
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.block.name())?;
+        let states = &self.block.properties().states;
+        if states.is_empty() {
+            return Ok(());
+        }
+        let values: Vec<String> = states.iter().map(|p| {
+            let value = match p {
+                StateProperty::Facing => String::from(face_name(self.facing())),
+                StateProperty::Axis => format!("{:?}", self.axis()).to_lowercase(),
+                StateProperty::Half => format!("{:?}", self.half()).to_lowercase(),
+                StateProperty::Variant => self.variant().to_string(),
+            };
+            format!("{}={}", p.name(), value)
+        }).collect();
+        write!(f, "[{}]", values.join(","))
+    }
+}
+
+/// parses the format written by `Display`, e.g. `log[axis=x]`. omitted properties keep their default value.
+impl FromStr for BlockState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, properties) = match s.split_once('[') {
+            Some((name, rest)) => (name, rest.strip_suffix(']').ok_or_else(|| format!("missing `]` in `{}`", s))?),
+            None => (s, ""),
+        };
+        let block = BlockType::from_name(name.trim()).ok_or_else(|| format!("unknown block type `{}`", name.trim()))?;
+        let mut state = BlockState::new(block);
+        for assignment in properties.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            let (key, value) = assignment.split_once('=').ok_or_else(|| format!("expected `property=value`, found `{}`", assignment))?;
+            let (key, value) = (key.trim(), value.trim());
+            let property = StateProperty::from_name(key).filter(|&p| state.has_property(p))
+                .ok_or_else(|| format!("block type `{}` has no property `{}`", block.name(), key))?;
+            let invalid = || format!("invalid value `{}` for property `{}`", value, key);
+            state = match property {
+                StateProperty::Facing => state.with_facing(Face::all().into_iter().find(|&f| face_name(f) == value).ok_or_else(invalid)?),
+                StateProperty::Axis => state.with_axis(match value {
+                    "x" => BlockAxis::X,
+                    "y" => BlockAxis::Y,
+                    "z" => BlockAxis::Z,
+                    _ => return Err(invalid()),
+                }),
+                StateProperty::Half => state.with_half(match value {
+                    "bottom" => Half::Bottom,
+                    "top" => Half::Top,
+                    "double" => Half::Double,
+                    _ => return Err(invalid()),
+                }),
+                StateProperty::Variant => state.with_variant(value.parse().ok().filter(|&v| v < 16).ok_or_else(invalid)?),
+            };
+        }
+        Ok(state)
+    }
+}
@@ -0,0 +1,499 @@
+// MagicaVoxel `.vox` files, see https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+//
+// only the chunks describing geometry are read: SIZE, XYZI, RGBA and the translations of the scene graph
+// (nTRN, nGRP, nSHP). rotations of scene graph nodes, materials and layers are ignored.
+//
+// MagicaVoxel is z-up, the world is y-up: a voxel at (x, y, z) in the file is the block at (x, z, -y) in the world.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::config;
+use super::World;
+use super::block::BlockType;
+use super::error::WorldError;
+use super::icoords::ICoords;
+use super::persistence::invalid_data;
+use super::region::{Clipboard, Region};
+use super::registry::registry;
+use super::size::Size3D;
+use super::state::BlockState;
+
+pub const VOX_EXTENSION: &str = "vox";
+/// maximum size of a model along each axis
+pub const MAX_MODEL_SIZE: i64 = 256;
+
+const VOX_VERSION: i32 = 150;
+
+/// a single model of a `.vox` file
+#[derive(Clone, Debug)]
+pub struct VoxModel {
+    pub size: [i32; 3],
+    /// (x, y, z, color index), color index 0 is never used
+    pub voxels: Vec<[u8; 4]>,
+    /// position of the minimum corner of the model in the scene
+    pub position: [i32; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// rgba color of each color index, index 0 is unused
+    pub palette: [[u8; 4]; 256],
+}
+
+/// the palette MagicaVoxel uses if a file has no RGBA chunk
+fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let mut i = 1;
+    let cube = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    for r in cube {
+        for g in cube {
+            for b in cube {
+                if r == 0 && g == 0 && b == 0 {
+                    continue;
+                }
+                palette[i] = [r, g, b, 0xff];
+                i += 1;
+            }
+        }
+    }
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channel in [2, 1, 0, 3] {
+        for v in ramp {
+            palette[i] = match channel {
+                3 => [v, v, v, 0xff],
+                _ => {
+                    let mut c = [0, 0, 0, 0xff];
+                    c[channel] = v;
+                    c
+                }
+            };
+            i += 1;
+        }
+    }
+    palette
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| invalid_data(String::from("unexpected end of vox file")))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.i32()?;
+        let bytes = self.bytes(len.max(0) as usize)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        let n = self.i32()?;
+        (0..n).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+}
+
+enum SceneNode {
+    Transform { translation: [i32; 3], child: i32 },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+fn parse_translation(attributes: &HashMap<String, String>) -> [i32; 3] {
+    let mut t = [0; 3];
+    if let Some(value) = attributes.get("_t") {
+        for (i, v) in value.split_whitespace().take(3).enumerate() {
+            t[i] = v.parse().unwrap_or(0);
+        }
+    }
+    t
+}
+
+/// adds the translation of every model reachable from `node` to `positions`
+fn resolve_scene(nodes: &HashMap<i32, SceneNode>, node: i32, translation: [i32; 3], positions: &mut HashMap<i32, [i32; 3]>, depth: usize) {
+    // guards against cycles in malformed files
+    if depth > 64 {
+        return;
+    }
+    match nodes.get(&node) {
+        Some(SceneNode::Transform { translation: t, child }) => {
+            let translation = [translation[0] + t[0], translation[1] + t[1], translation[2] + t[2]];
+            resolve_scene(nodes, *child, translation, positions, depth + 1);
+        },
+        Some(SceneNode::Group { children }) => {
+            for &child in children {
+                resolve_scene(nodes, child, translation, positions, depth + 1);
+            }
+        },
+        Some(SceneNode::Shape { models }) => {
+            for &model in models {
+                positions.insert(model, translation);
+            }
+        },
+        None => {},
+    }
+}
+
+fn write_i32(w: &mut Vec<u8>, v: i32) {
+    w.extend_from_slice(&v.to_le_bytes());
+}
+
+fn write_string(w: &mut Vec<u8>, s: &str) {
+    write_i32(w, s.len() as i32);
+    w.extend_from_slice(s.as_bytes());
+}
+
+fn write_dict(w: &mut Vec<u8>, entries: &[(&str, String)]) {
+    write_i32(w, entries.len() as i32);
+    for (key, value) in entries {
+        write_string(w, key);
+        write_string(w, value);
+    }
+}
+
+fn write_chunk(w: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    w.extend_from_slice(id);
+    write_i32(w, content.len() as i32);
+    write_i32(w, children.len() as i32);
+    w.extend_from_slice(content);
+    w.extend_from_slice(children);
+}
+
+impl VoxFile {
+    pub fn read(data: &[u8]) -> io::Result<Self> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(4)? != b"VOX " {
+            return Err(invalid_data(String::from("not a vox file")));
+        }
+        r.i32()?;
+
+        let mut sizes = Vec::new();
+        let mut models = Vec::new();
+        let mut palette = default_palette();
+        let mut nodes = HashMap::new();
+        while r.pos < data.len() {
+            let id: [u8; 4] = r.bytes(4)?.try_into().unwrap();
+            let content_size = r.i32()?.max(0) as usize;
+            // the children of MAIN follow as regular chunks, all other chunks have no children
+            r.i32()?;
+            let mut c = Reader { data: r.bytes(content_size)?, pos: 0 };
+            match &id {
+                b"SIZE" => sizes.push([c.i32()?, c.i32()?, c.i32()?]),
+                b"XYZI" => {
+                    let n = c.i32()?.max(0) as usize;
+                    let voxels: Vec<[u8; 4]> = c.bytes(4 * n)?.chunks_exact(4).map(|v| [v[0], v[1], v[2], v[3]]).collect();
+                    let size = *sizes.get(models.len()).ok_or_else(|| invalid_data(String::from("XYZI chunk without SIZE chunk")))?;
+                    models.push(VoxModel { size, voxels, position: [0; 3] });
+                },
+                b"RGBA" => {
+                    for i in 0..255 {
+                        palette[i + 1] = c.bytes(4)?.try_into().unwrap();
+                    }
+                },
+                b"nTRN" => {
+                    let node = c.i32()?;
+                    c.dict()?;
+                    let child = c.i32()?;
+                    c.i32()?;
+                    c.i32()?;
+                    let n_frames = c.i32()?;
+                    let translation = if n_frames > 0 { parse_translation(&c.dict()?) } else { [0; 3] };
+                    nodes.insert(node, SceneNode::Transform { translation, child });
+                },
+                b"nGRP" => {
+                    let node = c.i32()?;
+                    c.dict()?;
+                    let n = c.i32()?;
+                    let children = (0..n).map(|_| c.i32()).collect::<io::Result<_>>()?;
+                    nodes.insert(node, SceneNode::Group { children });
+                },
+                b"nSHP" => {
+                    let node = c.i32()?;
+                    c.dict()?;
+                    let n = c.i32()?;
+                    let mut shape_models = Vec::new();
+                    for _ in 0..n {
+                        shape_models.push(c.i32()?);
+                        c.dict()?;
+                    }
+                    nodes.insert(node, SceneNode::Shape { models: shape_models });
+                },
+                _ => {},
+            }
+        }
+
+        // without a scene graph all models are placed at the origin
+        let mut centers = HashMap::new();
+        resolve_scene(&nodes, 0, [0; 3], &mut centers, 0);
+        for (i, model) in models.iter_mut().enumerate() {
+            // translations refer to the center of the model
+            let center = centers.get(&(i as i32)).copied().unwrap_or([0; 3]);
+            model.position = [0, 1, 2].map(|a| center[a] - model.size[a] / 2);
+        }
+        Ok(VoxFile { models, palette })
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+        let mut pack = Vec::new();
+        write_i32(&mut pack, self.models.len() as i32);
+        write_chunk(&mut children, b"PACK", &pack, &[]);
+
+        for model in &self.models {
+            let mut size = Vec::new();
+            for d in model.size {
+                write_i32(&mut size, d);
+            }
+            write_chunk(&mut children, b"SIZE", &size, &[]);
+            let mut xyzi = Vec::new();
+            write_i32(&mut xyzi, model.voxels.len() as i32);
+            for v in &model.voxels {
+                xyzi.extend_from_slice(v);
+            }
+            write_chunk(&mut children, b"XYZI", &xyzi, &[]);
+        }
+
+        // scene graph: root transform 0 -> group 1 -> (transform 2 + 2i -> shape 3 + 2i) for every model i
+        let mut node = Vec::new();
+        write_i32(&mut node, 0);
+        write_dict(&mut node, &[]);
+        for v in [1, -1, 0, 1] {
+            write_i32(&mut node, v);
+        }
+        write_dict(&mut node, &[]);
+        write_chunk(&mut children, b"nTRN", &node, &[]);
+
+        let mut group = Vec::new();
+        write_i32(&mut group, 1);
+        write_dict(&mut group, &[]);
+        write_i32(&mut group, self.models.len() as i32);
+        for i in 0..self.models.len() {
+            write_i32(&mut group, 2 + 2 * i as i32);
+        }
+        write_chunk(&mut children, b"nGRP", &group, &[]);
+
+        for (i, model) in self.models.iter().enumerate() {
+            let center = [0, 1, 2].map(|a| model.position[a] + model.size[a] / 2);
+            let mut transform = Vec::new();
+            write_i32(&mut transform, 2 + 2 * i as i32);
+            write_dict(&mut transform, &[]);
+            for v in [3 + 2 * i as i32, -1, 0, 1] {
+                write_i32(&mut transform, v);
+            }
+            write_dict(&mut transform, &[("_t", format!("{} {} {}", center[0], center[1], center[2]))]);
+            write_chunk(&mut children, b"nTRN", &transform, &[]);
+
+            let mut shape = Vec::new();
+            write_i32(&mut shape, 3 + 2 * i as i32);
+            write_dict(&mut shape, &[]);
+            write_i32(&mut shape, 1);
+            write_i32(&mut shape, i as i32);
+            write_dict(&mut shape, &[]);
+            write_chunk(&mut children, b"nSHP", &shape, &[]);
+        }
+
+        let mut rgba = Vec::new();
+        for i in 0..256 {
+            rgba.extend_from_slice(&self.palette[(i + 1) % 256]);
+        }
+        write_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+        let mut file = Vec::from(*b"VOX ");
+        write_i32(&mut file, VOX_VERSION);
+        write_chunk(&mut file, b"MAIN", &[], &children);
+        file
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.write())
+    }
+
+    /// converts the voxels to blocks, using the registered block type with the closest color for every color index.
+    /// the minimum corner of the bounding box of all models is at the minimum corner of the clipboard.
+    /// returns `None` if the file contains no voxels, and an error if the models span more than `config::MAX_EDIT_VOLUME` voxels.
+    pub fn to_clipboard(&self) -> Result<Option<Clipboard>, WorldError> {
+        let voxels = self.models.iter().flat_map(|m| m.voxels.iter().map(move |v| {
+            ([0, 1, 2].map(|a| m.position[a] as i64 + v[a] as i64), v[3])
+        }));
+        let (mut min, mut max) = ([i64::MAX; 3], [i64::MIN; 3]);
+        for (p, _) in voxels.clone() {
+            for a in 0..3 {
+                min[a] = min[a].min(p[a]);
+                max[a] = max[a].max(p[a]);
+            }
+        }
+        if min[0] > max[0] {
+            return Ok(None);
+        }
+
+        // the positions of the models are not trusted, nothing is allocated before the volume has been checked
+        let size = Size3D { x: (max[0] - min[0] + 1) as u64, y: (max[2] - min[2] + 1) as u64, z: (max[1] - min[1] + 1) as u64 };
+        match size.x.checked_mul(size.y).and_then(|v| v.checked_mul(size.z)) {
+            Some(volume) if volume <= config::MAX_EDIT_VOLUME => (),
+            volume => return Err(WorldError::TooLarge(volume)),
+        }
+        let mut clipboard = Clipboard::new(size);
+        let mut blocks: HashMap<u8, BlockState> = HashMap::new();
+        for (p, index) in voxels {
+            let block = *blocks.entry(index).or_insert_with(|| BlockState::new(closest_block(self.palette[index as usize])));
+            clipboard.set(ICoords::new(p[0] - min[0], p[2] - min[2], max[1] - p[1]), block);
+        }
+        Ok(Some(clipboard))
+    }
+
+    /// converts the blocks to voxels, colored by `BlockType::color`.
+    /// the clipboard is split into models of at most `MAX_MODEL_SIZE` voxels along each axis.
+    pub fn from_clipboard(clipboard: &Clipboard) -> Self {
+        let size = clipboard.size();
+        // size in vox coordinates
+        let vox_size = [size.x as i64, size.z as i64, size.y as i64];
+        let mut palette = [[0u8; 4]; 256];
+        let mut colors: HashMap<[u8; 4], u8> = HashMap::new();
+        let mut models = Vec::new();
+
+        let n_models = vox_size.map(|d| (d + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE);
+        for mx in 0..n_models[0] {
+            for my in 0..n_models[1] {
+                for mz in 0..n_models[2] {
+                    let origin = [mx, my, mz].map(|m| m * MAX_MODEL_SIZE);
+                    let model_size = [0, 1, 2].map(|a| (vox_size[a] - origin[a]).min(MAX_MODEL_SIZE));
+                    let mut voxels = Vec::new();
+                    for c in (Size3D { x: model_size[0] as u64, y: model_size[1] as u64, z: model_size[2] as u64 }) {
+                        let v = [origin[0] + c.x, origin[1] + c.y, origin[2] + c.z];
+                        let block = clipboard.get(ICoords::new(v[0], v[2], vox_size[1] - 1 - v[1]));
+                        let Some(color) = block.block.color() else {
+                            continue;
+                        };
+                        let rgba = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                        let index = match colors.get(&rgba) {
+                            Some(&index) => index,
+                            None if colors.len() < 255 => {
+                                let index = colors.len() as u8 + 1;
+                                palette[index as usize] = rgba;
+                                colors.insert(rgba, index);
+                                index
+                            },
+                            // the palette is full, use the closest color
+                            None => closest_color(&palette[1..], rgba) as u8 + 1,
+                        };
+                        voxels.push([c.x as u8, c.y as u8, c.z as u8, index]);
+                    }
+                    if !voxels.is_empty() {
+                        models.push(VoxModel { size: model_size.map(|d| d as i32), voxels, position: origin.map(|o| o as i32) });
+                    }
+                }
+            }
+        }
+        // MagicaVoxel does not open files without models
+        if models.is_empty() {
+            models.push(VoxModel { size: [1, 1, 1], voxels: Vec::new(), position: [0; 3] });
+        }
+        VoxFile { models, palette }
+    }
+}
+
+impl World {
+    /// places the models of `vox` with the minimum corner of their bounding box at `position`.
+    /// empty voxels do not replace blocks. returns the number of changed blocks.
+    pub fn import_vox(&mut self, vox: &VoxFile, position: ICoords) -> Result<usize, WorldError> {
+        match vox.to_clipboard()? {
+            Some(clipboard) => self.paste(&clipboard, position, false),
+            None => Ok(0),
+        }
+    }
+
+    /// converts the blocks in `region` to a vox file, see `VoxFile::from_clipboard`
+    pub fn export_vox(&self, region: Region) -> Result<VoxFile, WorldError> {
+        Ok(VoxFile::from_clipboard(&self.copy(region)?))
+    }
+}
+
+fn color_distance(a: [u8; 4], b: [u8; 4]) -> i32 {
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2)).sum()
+}
+
+/// index of the color in `palette` closest to `color`
+fn closest_color(palette: &[[u8; 4]], color: [u8; 4]) -> usize {
+    (0..palette.len()).min_by_key(|&i| color_distance(palette[i], color)).unwrap()
+}
+
+/// the block type filling its whole voxel whose color is closest to `color`
+fn closest_block(color: [u8; 4]) -> BlockType {
+    registry().block_types()
+        .filter(|&b| BlockState::new(b).is_full())
+        .filter_map(|b| b.color().map(|c| (b, c.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))))
+        .min_by_key(|&(_, c)| color_distance(c, color))
+        .map_or(BlockType::NO_BLOCK, |(b, _)| b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str) -> BlockState {
+        BlockState::new(BlockType::from_name(name).unwrap())
+    }
+
+    fn voxel_at(position: [i32; 3]) -> VoxModel {
+        VoxModel { size: [1, 1, 1], voxels: vec![[0, 0, 0, 1]], position }
+    }
+
+    #[test]
+    fn round_trip_through_several_models() {
+        // larger than a model along x and z, which is y in vox coordinates
+        let size = Size3D { x: 260, y: 2, z: 258 };
+        let blocks = [BlockState::NO_BLOCK, block("stone"), block("dirt"), block("sand")];
+        let pattern = |c: ICoords| blocks[(c.x + 2 * c.y + 3 * c.z).rem_euclid(4) as usize];
+        let mut clipboard = Clipboard::new(size);
+        for c in size {
+            clipboard.set(c, pattern(c));
+        }
+        // the bounding box of the voxels is the whole clipboard
+        let (first, last) = (ICoords::new(0, 0, 0), ICoords::new(259, 1, 257));
+        clipboard.set(first, block("stone"));
+        clipboard.set(last, block("stone"));
+
+        let vox = VoxFile::from_clipboard(&clipboard);
+        assert_eq!(vox.models.len(), 4);
+        assert!(vox.models.iter().all(|m| m.size.iter().all(|&d| d as i64 <= MAX_MODEL_SIZE)));
+        let read = VoxFile::read(&vox.write()).unwrap();
+        assert_eq!(read.models.len(), 4);
+        let copied = read.to_clipboard().unwrap().unwrap();
+        assert_eq!(copied.size(), size);
+        for c in size {
+            let expected = if c == first || c == last { block("stone") } else { pattern(c) };
+            assert_eq!(copied.get(c), expected, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn models_spanning_too_many_voxels_are_rejected() {
+        let palette = default_palette();
+        let apart = VoxFile { models: vec![voxel_at([i32::MIN, 0, 0]), voxel_at([i32::MAX, 0, 0])], palette };
+        let read = VoxFile::read(&apart.write()).unwrap();
+        assert_eq!(read.to_clipboard().err(), Some(WorldError::TooLarge(Some(1 << 32))));
+
+        let corners = VoxFile { models: vec![voxel_at([i32::MIN; 3]), voxel_at([i32::MAX; 3])], palette };
+        assert_eq!(corners.to_clipboard().err(), Some(WorldError::TooLarge(None)));
+
+        let empty = VoxFile { models: vec![VoxModel { size: [1, 1, 1], voxels: Vec::new(), position: [0; 3] }], palette };
+        assert!(empty.to_clipboard().unwrap().is_none());
+    }
+}